@@ -1,6 +1,7 @@
 pub mod consts;
 pub mod check;
 pub mod error;
+pub mod repair;
 
 mod implement;
 
@@ -8,6 +9,7 @@ pub use consts::*;
 use std::collections;
 use crate::util::graph::Node;
 use std::rc::Rc;
+use serde::Serialize;
 
 #[derive(Debug, PartialEq)]
 pub struct SuperBlock {
@@ -29,12 +31,60 @@ pub struct SuperBlock {
     pub bmapstart: u32,
 }
 
+// Header of the log at logstart, like struct logheader in xv6
+#[derive(Debug, PartialEq)]
+pub struct LogHeader {
+    // Number of committed blocks, 0 if there is no transaction to install
+    pub n: u32,
+    // Home location of the i-th logged block, which is stored at logstart + 1 + i
+    // Every slot in the header block is kept, including those beyond n
+    pub block: Vec<u32>,
+}
+
+// Layout of the regions on disk, derived from the superblock
+// [ boot block | super block | log | inode blocks | free bit map | data blocks ]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Geometry {
+    // Size of file system image (blocks)
+    pub size: u32,
+    // Number of log blocks
+    pub nlog: u32,
+    // Block number of first log block
+    pub logstart: u32,
+    // Number of inodes
+    pub ninodes: u32,
+    // Number of inode blocks
+    pub ninodeblocks: u32,
+    // Block number of first inode block
+    pub inodestart: u32,
+    // Number of bitmap blocks
+    pub nbitmap: u32,
+    // Block number of first free map block
+    pub bmapstart: u32,
+    // Number of data blocks
+    pub nblocks: u32,
+    // Block number of first data block
+    pub datastart: u32,
+}
+
+// Block number on disk
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize)]
+#[serde(transparent)]
+pub struct BlockNo(pub u32);
+
+// Index of a data block in FS::data, i.e. BlockNo - datastart
+// Only used to access FS::data, everything else refers to blocks by BlockNo
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct DataIndex(pub u32);
+
 #[derive(Debug, PartialEq)]
 pub enum FileType {
     UNUSED,
     DIR,
     FILE,
     DEV,
+    // Unknown type on disk (only produced by lenient parsing)
+    Invalid(u16),
 }
 
 #[derive(Debug, PartialEq)]
@@ -50,18 +100,47 @@ pub struct Dinode {
     // Size of file (bytes)
     pub size: u32,
     // Data block addresses
-    pub addrs: [Option<u32>; NDIRECT + 1],
+    pub addrs: [Option<BlockNo>; NDIRECT + 1],
+    // Block numbers on disk
+    // Differs from addrs only where lenient parsing dropped an invalid address
+    pub raw_addrs: [u32; NDIRECT + 1],
 }
 
 #[derive(Debug, PartialEq)]
 pub struct Dirent {
     // Inode number
     pub inum: u16,
-    // File name
+    // File name on disk, terminated by NUL unless it is DIRSIZ bytes long
+    pub raw_name: [u8; DIRSIZ],
+    // File name up to the first NUL, with invalid UTF-8 replaced, as namecmp in xv6 compares
     pub name: String,
+    // Byte offset of the entry in the directory
+    pub offset: usize,
 }
 
-#[derive(Debug, PartialEq)]
+// Reference from an inode to a data block
+// Variants are ordered so that the indirect block itself comes first
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum BlockRef {
+    // dinode.addrs[NDIRECT]
+    IndirectBlock { inum: u16 },
+    // dinode.addrs[slot]
+    Direct { inum: u16, slot: usize },
+    // slot-th entry of the indirect block
+    Indirect { inum: u16, slot: usize },
+}
+
+// Number of references to a data block
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct RefCount {
+    // by allocated inodes
+    pub live: u32,
+    // by unused inodes, left by an incomplete itrunc
+    pub stale: u32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
 pub enum BlockStatus {
     Free,
     Allocated,
@@ -69,12 +148,19 @@ pub enum BlockStatus {
 
 pub struct FS {
     pub superblock: SuperBlock,
+    pub geometry: Geometry,
+    // None if the log is empty or its header is outside the image
+    pub log: Option<LogHeader>,
     pub dinodes: Vec<Dinode>,
+    // Indexed by BlockNo
     pub bitmap: Vec<BlockStatus>,
+    // Indexed by DataIndex
     pub data: Vec<Vec<u8>>,
     directory_tree: Rc<Node<u16>>,
     inum_to_dirents: collections::HashMap<u16, Option<Vec<Dirent>>>,
     // Note that references by "." and ".." are excluded.
     // In a invalid file system, .value and .parent may not match ".", "..", respectively
     inum_to_node: collections::HashMap<u16, Rc<Node<u16>>>,
+    // Extra links to directories found by construct_directory_tree
+    tree_errors: Vec<error::FSError>,
 }
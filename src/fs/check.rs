@@ -1,7 +1,9 @@
 mod consts;
 mod check_superblock;
+mod check_log;
 mod check_block_usage;
 mod check_inode;
+mod check_root;
 mod check_directory;
 
 use super::*;
@@ -2,13 +2,14 @@ use super::*;
 use crate::fs::error::FSError;
 
 impl FS {
-    fn count_datablock_ref(&self) -> collections::HashMap<u32, u32> {
-        let mut count = collections::HashMap::new();
-        for dinode in self.dinodes.iter() {
-            let addrs = self.get_all_addrs(dinode);
-            for addr in addrs {
-                *count.entry(addr).or_insert(0) += 1;
-            }
+    // Stale references do not keep a block in use, but they are counted so that no block is handed out twice
+    pub(crate) fn count_datablock_ref(&self) -> collections::HashMap<BlockNo, RefCount> {
+        let mut count: collections::HashMap<BlockNo, RefCount> = collections::HashMap::new();
+        for (addr, refs) in self.get_block_refs() {
+            count.entry(addr).or_default().live = refs.len() as u32;
+        }
+        for (addr, refs) in self.get_stale_block_refs() {
+            count.entry(addr).or_default().stale = refs.len() as u32;
         }
         count
     }
@@ -16,30 +17,45 @@ impl FS {
     // Check the number of references to data blocks
     pub fn check_datablock_ref(&self) -> Vec<FSError> {
         let mut errors = vec![];
-        let count = self.count_datablock_ref();
-        for (addr, v) in count.iter() {
-            if *v > 1 {
-                errors.push(FSError::MultipleRef(*addr));
+        let mut refs: Vec<_> = self.get_block_refs().into_iter().collect();
+        refs.sort();
+        for (addr, v) in refs {
+            if v.len() > 1 {
+                errors.push(FSError::MultipleRef(addr, v));
             }
         }
         errors
     }
 
-    // Check bitmap only for data blocks
+    // Check bitmap for every block: metadata blocks must be allocated like mkfs does,
+    // gaps between the regions are left to check_fields,
+    // data blocks must match the references, and bits beyond the image must be clear
     // Assume that the each data block references is at most 1
     pub fn check_bitmap(&self) -> Vec<FSError> {
         let mut errors = vec![];
         let count = self.count_datablock_ref();
-        for (i, bmap) in self.bitmap.iter().enumerate() {
-            if i < DATASTART {
-                continue;
+        let datastart = self.geometry.datastart as usize;
+        let size = self.geometry.size as usize;
+        for (i, bmap) in self.bitmap[..datastart.min(size)].iter().enumerate() {
+            let addr = BlockNo(i as u32);
+            let region = self.geometry.region(addr);
+            if *bmap != BlockStatus::Allocated && region != "unknown" {
+                errors.push(FSError::FreeMetadataBlock(region, addr));
             }
-            let addr = (i - DATASTART) as u32;
-            let v = count.get(&addr).unwrap_or(&0);
-            // *v > 1 is invalid, but this error is detected by other checkers
-            let status = if *v > 0 { BlockStatus::Allocated } else { BlockStatus::Free };
+        }
+
+        let padding: Vec<usize> = (size..self.bitmap.len()).filter(|i| self.bitmap[*i] != BlockStatus::Free).collect();
+        if let Some(first) = padding.first() {
+            errors.push(FSError::AllocatedPadding(BlockNo(*first as u32), padding.len()));
+        }
+
+        for (i, bmap) in self.bitmap.iter().enumerate().take(size).skip(datastart) {
+            let addr = BlockNo(i as u32);
+            let v = count.get(&addr).map_or(0, |count| count.live);
+            // v > 1 is invalid, but this error is detected by other checkers
+            let status = if v > 0 { BlockStatus::Allocated } else { BlockStatus::Free };
             if *bmap != status {
-                errors.push(FSError::IncorrectBitmap(addr, bmap));
+                errors.push(FSError::IncorrectBitmap(addr, *bmap));
             }
         }
         errors
@@ -2,134 +2,217 @@ use super::*;
 use crate::fs::error::FSError;
 
 impl FS {
-    fn check_current_directory_individual(&self, inum: u16) -> Result<(), FSError> {
-        let dirents = match self.get_dirents(&inum) {
-            Some(dirents) => dirents,
-            // not a directory
-            None => return Ok(()),
-        };
+    // First entry named `name`, which namei resolves
+    pub(crate) fn find_dirent(&self, inum: u16, name: &str) -> Option<&Dirent> {
+        self.get_dirents(&inum)?.iter().find(|dirent| dirent.name_bytes() == name.as_bytes())
+    }
 
-        let mut inum_from_dirents = 0;
-        for dirent in dirents {
-            if dirent.name == "." {
-                inum_from_dirents = dirent.inum;
-                break;
-            }
+    // Offsets of the entries named `name` outside of the slot at `canonical`
+    fn misplaced_offsets(&self, inum: u16, name: &str, canonical: usize) -> Vec<usize> {
+        self.get_dirents(&inum)
+            .into_iter()
+            .flatten()
+            .filter(|dirent| dirent.name_bytes() == name.as_bytes() && dirent.offset != canonical)
+            .map(|dirent| dirent.offset)
+            .collect()
+    }
+
+    fn check_current_directory_individual(&self, inum: u16) -> Vec<FSError> {
+        let mut errors = vec![];
+        // not a directory
+        if self.get_dirents(&inum).is_none() {
+            return errors;
         }
 
-        if inum_from_dirents == inum {
-            Ok(())
-        } else {
-            Err(FSError::IncorrectCurrentDirRef(inum))
+        let dirent = match self.find_dirent(inum, ".") {
+            Some(dirent) => dirent,
+            None => {
+                errors.push(FSError::MissingCurrentDirEntry(inum));
+                return errors;
+            }
+        };
+        for offset in self.misplaced_offsets(inum, ".", CURRENT_DIR_OFFSET) {
+            errors.push(FSError::MisplacedCurrentDirEntry(inum, offset));
         }
+        // The root is checked by check_root
+        if dirent.inum != inum && inum != ROOTINO {
+            errors.push(FSError::IncorrectCurrentDirRef(inum, dirent.inum));
+        }
+        errors
     }
 
     pub fn check_current_directory(&self) -> Vec<FSError> {
         let mut errors = vec![];
         for (inum, dinode) in self.dinodes.iter().enumerate() {
             if dinode.typ == FileType::DIR {
-                if let Err(e) = self.check_current_directory_individual(inum as u16) {
-                    errors.push(e);
-                }
+                errors.append(&mut self.check_current_directory_individual(inum as u16));
             }
         }
         errors
     }
 
-    fn check_parent_directory_individual(&self, inum: u16) -> Result<(), FSError> {
-        let dirents = match self.get_dirents(&inum) {
-            Some(dirents) => dirents,
-            // not a directory
-            None => return Ok(()),
-        };
+    fn check_parent_directory_individual(&self, inum: u16) -> Vec<FSError> {
+        let mut errors = vec![];
+        // not a directory
+        if self.get_dirents(&inum).is_none() {
+            return errors;
+        }
 
-        let mut inum_from_dirents = 0;
-        for dirent in dirents {
-            if dirent.name == ".." {
-                inum_from_dirents = dirent.inum;
-                break;
+        let dirent = match self.find_dirent(inum, "..") {
+            Some(dirent) => dirent,
+            None => {
+                errors.push(FSError::MissingParentDirEntry(inum));
+                return errors;
             }
+        };
+        for offset in self.misplaced_offsets(inum, "..", PARENT_DIR_OFFSET) {
+            errors.push(FSError::MisplacedParentDirEntry(inum, offset));
         }
 
         let dir = match self.get_node(&inum) {
             Some(dir) => dir,
-            None => return Err(FSError::DanglingInode(inum)),
+            None => {
+                errors.push(FSError::DanglingInode(inum));
+                return errors;
+            }
         };
         let parents = dir.parents.borrow();
         assert_eq!(parents.len(), 1);
         let parent = parents[0].upgrade().unwrap();
 
         let inum_from_node = parent.value;
-        if inum_from_dirents == inum_from_node {
-            Ok(())
-        } else {
-            Err(FSError::IncorrectParentDirRef(inum))
+        if dirent.inum != inum_from_node && inum != ROOTINO {
+            errors.push(FSError::IncorrectParentDirRef(inum, dirent.inum, inum_from_node));
         }
+        errors
     }
 
     pub fn check_parent_directory(&self) -> Vec<FSError> {
         let mut errors = vec![];
         for (inum, dinode) in self.dinodes.iter().enumerate() {
             if dinode.typ == FileType::DIR {
-                if let Err(e) = self.check_parent_directory_individual(inum as u16) {
-                    errors.push(e);
-                }
+                errors.append(&mut self.check_parent_directory_individual(inum as u16));
             }
         }
         errors
     }
 
-    fn check_directory_ref_individual(&self, inum: u16) -> Result<(), FSError> {
-        if self.dinodes[inum as usize].typ != FileType::DIR {
-            return Ok(());
-        }
-
-        let dir = match self.get_node(&inum) {
-            Some(dir) => dir,
-            None => return Err(FSError::DanglingInode(inum)),
+    // Entries in unreachable directories which link a directory in the directory tree
+    // Such entries in reachable directories are found by construct_directory_tree
+    fn check_directory_ref_individual(&self, inum: u16) -> Vec<FSError> {
+        let mut errors = vec![];
+        let dirents = match self.get_dirents(&inum) {
+            Some(dirents) => dirents,
+            None => return errors,
         };
-        if dir.parents.borrow().len() == 1 {
-            Ok(())
-        } else {
-            Err(FSError::InvalidDirRef(inum))
+        for dirent in dirents {
+            if dirent.name == "." || dirent.name == ".." || dirent.inum as usize >= self.dinodes.len() {
+                continue;
+            }
+            if self.dinodes[dirent.inum as usize].typ == FileType::DIR && self.get_node(&dirent.inum).is_some() {
+                errors.push(FSError::InvalidDirRef(inum, dirent.index(), dirent.name.clone(), dirent.inum));
+            }
         }
+        errors
     }
 
-    // Whether the directory is referenced only by its parent and children
-    pub fn check_directory_ref(&self) -> Result<(), FSError> {
+    // Whether each directory is linked by only one entry other than "." and ".."
+    pub fn check_directory_ref(&self) -> Vec<FSError> {
+        let mut errors = self.tree_errors.clone();
         for (inum, dinode) in self.dinodes.iter().enumerate() {
-            if dinode.typ == FileType::DIR {
-                self.check_directory_ref_individual(inum as u16)?;
+            if dinode.typ == FileType::DIR && self.get_node(&(inum as u16)).is_none() {
+                errors.append(&mut self.check_directory_ref_individual(inum as u16));
             }
         }
-        Ok(())
+        errors
     }
 
-    fn check_dirents_individual(&self, inum: u16) -> Result<(), FSError> {
+    fn check_dirents_individual(&self, inum: u16) -> Vec<FSError> {
+        let mut errors = vec![];
         let dirents = match self.get_dirents(&inum) {
             Some(dirents) => dirents,
             // not a directory
-            None => return Ok(()),
+            None => return errors,
         };
 
-        let valid = dirents.iter().all(|dirent| {
-            let i = dirent.inum;
-            self.dinodes[i as usize].typ != FileType::UNUSED
-        });
+        for dirent in dirents {
+            match self.dinodes.get(dirent.inum as usize) {
+                None => errors.push(FSError::InodeOutOfRange(inum, dirent.index(), dirent.name.clone(), dirent.inum)),
+                Some(dinode) if dinode.typ == FileType::UNUSED => {
+                    errors.push(FSError::InvalidInodeRef(inum, dirent.index(), dirent.name.clone(), dirent.inum))
+                }
+                Some(_) => {}
+            }
+        }
+        errors
+    }
+
+    // Whether every entry refers to an allocated inode
+    pub fn check_dirents(&self) -> Vec<FSError> {
+        let mut errors = vec![];
+        for (i, dinode) in self.dinodes.iter().enumerate() {
+            if dinode.typ == FileType::DIR {
+                errors.append(&mut self.check_dirents_individual(i as u16));
+            }
+        }
+        errors
+    }
+
+    // Problems with the name of a live entry, as xv6 would mis-resolve it by namei
+    fn check_dirent_name(&self, inum: u16, dirent: &Dirent) -> Vec<FSError> {
+        let mut errors = vec![];
+        let index = dirent.index();
+        let (name, rest) = dirent.raw_name.split_at(dirent.name_bytes().len());
+        if name.is_empty() {
+            errors.push(FSError::EmptyDirentName(inum, index));
+        }
+        if name.contains(&b'/') {
+            errors.push(FSError::SlashInDirentName(inum, index, dirent.name.clone()));
+        }
+        if !name.iter().all(|c| c.is_ascii_graphic() || *c == b' ') {
+            errors.push(FSError::NonPrintableDirentName(inum, index, dirent.name.clone()));
+        }
+        if rest.iter().any(|c| *c != 0) {
+            errors.push(FSError::GarbageAfterDirentName(inum, index, dirent.name.clone()));
+        }
+        errors
+    }
+
+    pub fn check_dirent_names(&self) -> Vec<FSError> {
+        let mut errors = vec![];
+        for i in 0..self.dinodes.len() {
+            if let Some(dirents) = self.get_dirents(&(i as u16)) {
+                for dirent in dirents {
+                    errors.append(&mut self.check_dirent_name(i as u16, dirent));
+                }
+            }
+        }
+        errors
+    }
 
-        if valid {
-            Ok(())
-        } else {
-            Err(FSError::InvalidInodeRef(inum))
+    // Entries which have the same name as an earlier entry, so namei never finds them
+    // Returns (earlier entry, later entry) pairs
+    pub(crate) fn duplicate_dirents(&self, inum: u16) -> Vec<(&Dirent, &Dirent)> {
+        let mut duplicates = vec![];
+        let mut first: collections::HashMap<&[u8], &Dirent> = collections::HashMap::new();
+        for dirent in self.get_dirents(&inum).into_iter().flatten() {
+            match first.get(dirent.name_bytes()) {
+                Some(earlier) => duplicates.push((*earlier, dirent)),
+                None => {
+                    first.insert(dirent.name_bytes(), dirent);
+                }
+            }
         }
+        duplicates
     }
 
-    pub fn check_dirents(&self) -> Result<(), FSError> {
-        for (i, dindoe) in self.dinodes.iter().enumerate() {
-            if dindoe.typ == FileType::DIR {
-                self.check_dirents_individual(i as u16)?;
+    pub fn check_duplicate_names(&self) -> Vec<FSError> {
+        let mut errors = vec![];
+        for i in 0..self.dinodes.len() {
+            for (earlier, later) in self.duplicate_dirents(i as u16) {
+                errors.push(FSError::DuplicateDirentName(i as u16, later.name.clone(), earlier.offset, earlier.inum, later.offset, later.inum));
             }
         }
-        Ok(())
+        errors
     }
 }
@@ -1,5 +1,6 @@
 use super::*;
 use crate::fs::error::FSError;
+use crate::parser;
 
 impl Dinode {
     pub fn has_valid_device_numbers(&self) -> bool {
@@ -13,6 +14,57 @@ impl Dinode {
 }
 
 impl FS {
+    fn check_file_type_individual(&self, inum: u16) -> Result<(), FSError> {
+        match self.dinodes[inum as usize].typ {
+            FileType::Invalid(typ) => Err(FSError::InvalidFileType(inum, typ)),
+            _ => Ok(()),
+        }
+    }
+
+    pub fn check_file_types(&self) -> Vec<FSError> {
+        let mut errors = vec![];
+        for i in 0..self.dinodes.len() {
+            if let Err(e) = self.check_file_type_individual(i as u16) {
+                errors.push(e);
+            }
+        }
+        errors
+    }
+
+    // An inode may have several invalid addresses, so all of them are returned
+    fn check_addrs_range_individual(&self, inum: u16) -> Vec<FSError> {
+        let mut errors = vec![];
+        let dinode = &self.dinodes[inum as usize];
+        for (slot, block) in dinode.raw_addrs.iter().enumerate() {
+            if *block != 0 && !self.geometry.in_data_region(*block) {
+                let r = if slot == NDIRECT { BlockRef::IndirectBlock { inum } } else { BlockRef::Direct { inum, slot } };
+                errors.push(FSError::BlockOutOfRange(r, *block));
+            }
+        }
+
+        if let Some(addr) = dinode.addrs[NDIRECT] {
+            let blocks = parser::parse_indirect_block(self.block(addr));
+            for (slot, block) in blocks.iter().enumerate() {
+                if *block != 0 && !self.geometry.in_data_region(*block) {
+                    errors.push(FSError::BlockOutOfRange(BlockRef::Indirect { inum, slot }, *block));
+                }
+            }
+        }
+        errors
+    }
+
+    // Only reports something for images parsed in lenient mode
+    pub fn check_addrs_range(&self) -> Vec<FSError> {
+        let mut errors = vec![];
+        // Addresses left in unused inodes are reported by check_unused_inodes
+        for (i, dinode) in self.dinodes.iter().enumerate() {
+            if dinode.typ != FileType::UNUSED {
+                errors.append(&mut self.check_addrs_range_individual(i as u16));
+            }
+        }
+        errors
+    }
+
     fn check_device_numbers_individual(&self, inum: u16) -> Result<(), FSError> {
         let dinode = &self.dinodes[inum as usize];
         if dinode.typ != FileType::DEV {
@@ -21,7 +73,7 @@ impl FS {
         if dinode.has_valid_device_numbers() {
             return Ok(());
         }
-        Err(FSError::InvalidDevice(inum as u16))
+        Err(FSError::InvalidDevice(inum))
     }
 
     pub fn check_device_numbers(&self) -> Vec<FSError> {
@@ -36,48 +88,105 @@ impl FS {
         errors
     }
 
-    fn check_addrs_ref_individual(&self, inum: u16) -> Result<(), FSError> {
-        let dinode = &self.dinodes[inum as usize];
-        let valid = self.get_all_addrs(dinode).iter().all(|addr|
-            self.bitmap[*addr as usize] == BlockStatus::Allocated
-        );
-
-        if valid {
-            Ok(())
-        } else {
-            Err(FSError::InvalidDataBlockRef(inum))
-        }
+    fn check_addrs_ref_individual(&self, inum: u16) -> Vec<FSError> {
+        self.get_inode_refs(inum)
+            .into_iter()
+            .filter(|(_, addr)| self.block_status(*addr) != BlockStatus::Allocated)
+            .map(|(r, addr)| FSError::InvalidDataBlockRef(r, addr))
+            .collect()
     }
 
     // Assuming the bitmap is valid
     pub fn check_addrs_ref(&self) -> Vec<FSError> {
         let mut errors = vec![];
-        for i in 0..self.dinodes.len() {
-            if let Err(e) = self.check_addrs_ref_individual(i as u16) {
-                errors.push(e);
+        // Addresses left in unused inodes are reported by check_unused_inodes
+        for (i, dinode) in self.dinodes.iter().enumerate() {
+            if dinode.typ != FileType::UNUSED {
+                errors.append(&mut self.check_addrs_ref_individual(i as u16));
             }
         }
         errors
     }
 
-    // FIXME: Raise error about valid file system
-    fn check_addrs_len_individual(&self, inum: u16) -> Result<(), FSError> {
+    // Fields of an unused inode which are not zero, as ialloc expects a cleared inode
+    pub(crate) fn dirty_fields(dinode: &Dinode) -> Vec<&'static str> {
+        let mut fields = vec![];
+        if dinode.nlink != 0 {
+            fields.push("nlink");
+        }
+        if dinode.size != 0 {
+            fields.push("size");
+        }
+        if dinode.major != 0 {
+            fields.push("major");
+        }
+        if dinode.minor != 0 {
+            fields.push("minor");
+        }
+        if dinode.raw_addrs.iter().any(|addr| *addr != 0) {
+            fields.push("addrs");
+        }
+        fields
+    }
+
+    // Unused inodes must be zeroed like iput does after itrunc
+    pub fn check_unused_inodes(&self) -> Vec<FSError> {
+        let mut errors = vec![];
+        for (i, dinode) in self.dinodes.iter().enumerate() {
+            if dinode.typ != FileType::UNUSED {
+                continue;
+            }
+            let fields = Self::dirty_fields(dinode);
+            if !fields.is_empty() {
+                errors.push(FSError::HalfFreedInode(i as u16, fields, self.get_inode_refs(i as u16).len()));
+            }
+        }
+        errors
+    }
+
+    // The blocks of a file are exactly those within its size, like mkfs and writei in xv6 produce
+    fn check_addrs_len_individual(&self, inum: u16) -> Vec<FSError> {
+        let mut errors = vec![];
         let dinode = &self.dinodes[inum as usize];
-        let correct = (dinode.size as f64 / BSIZE as f64).ceil() as usize;
-        let len = self.get_all_addrs(dinode).len();
+        let too_large = dinode.size as usize > MAXFILE * BSIZE;
+        if too_large {
+            errors.push(FSError::FileTooLarge(inum, dinode.size));
+        }
+        if dinode.typ == FileType::DIR && !(dinode.size as usize).is_multiple_of(DIRENTSIZE) {
+            errors.push(FSError::InvalidDirectorySize(inum, dinode.size));
+        }
 
-        if len == correct {
-            Ok(())
-        } else {
-            Err(FSError::InvalidNumberOfDataBlockRef(inum, dinode.size, len))
+        // Number of blocks within the size
+        let n = (dinode.size as usize).div_ceil(BSIZE).min(MAXFILE);
+        // Holes are meaningless if the size itself is broken
+        let holes = self.get_file_blocks(dinode).into_iter().filter(|addr| addr.is_none()).count();
+        if holes > 0 && !too_large {
+            errors.push(FSError::MissingBlocks(inum, holes));
+        }
+        for (fbn, addr) in dinode.addrs[..NDIRECT].iter().enumerate().skip(n) {
+            if let Some(addr) = addr {
+                errors.push(FSError::BlockBeyondSize(inum, fbn, *addr));
+            }
+        }
+        if let Some(addr) = dinode.addrs[NDIRECT] {
+            if n <= NDIRECT {
+                errors.push(FSError::IndirectBlockBeyondSize(inum, addr));
+            } else if let Ok(addrs_indirect) = parser::parse_indirect_reference_block(self.block(addr), addr, &self.geometry, parser::ParseMode::Lenient) {
+                for (i, addr) in addrs_indirect.into_iter().enumerate().skip(n - NDIRECT) {
+                    if let Some(addr) = addr {
+                        errors.push(FSError::BlockBeyondSize(inum, NDIRECT + i, addr));
+                    }
+                }
+            }
         }
+        errors
     }
 
     pub fn check_addrs_len(&self) -> Vec<FSError> {
         let mut errors = vec![];
-        for i in 0..self.dinodes.len() {
-            if let Err(e) = self.check_addrs_len_individual(i as u16) {
-                errors.push(e);
+        for (i, dinode) in self.dinodes.iter().enumerate() {
+            if dinode.typ != FileType::UNUSED {
+                errors.append(&mut self.check_addrs_len_individual(i as u16));
             }
         }
         errors
@@ -95,7 +204,7 @@ impl FS {
                 if dinode.nlink == ref_count {
                     Ok(())
                 } else {
-                    Err(FSError::IncorrectNLink(inum, dinode.nlink))
+                    Err(FSError::IncorrectNLink(inum, dinode.nlink, ref_count))
                 }
             }
             FileType::DIR => {
@@ -118,12 +227,14 @@ impl FS {
                 if dinode.nlink == ref_count {
                     Ok(())
                 } else {
-                    Err(FSError::IncorrectNLink(inum, dinode.nlink))
+                    Err(FSError::IncorrectNLink(inum, dinode.nlink, ref_count))
                 }
             }
             // TODO: check nlink for device file
             FileType::DEV => Ok(()),
             FileType::UNUSED => Ok(()),
+            // reported by check_file_types
+            FileType::Invalid(_) => Ok(()),
         }
     }
 
@@ -0,0 +1,46 @@
+use super::*;
+use crate::fs::error::FSError;
+
+impl LogHeader {
+    // Number of blocks the log can hold after the header block
+    pub fn capacity(&self, geo: &Geometry) -> u32 {
+        geo.nlog.saturating_sub(1).min(self.block.len() as u32)
+    }
+}
+
+impl Geometry {
+    // Whether a logged block can be installed there: inode, bitmap and data blocks are written through the log
+    pub fn is_loggable(&self, block: u32) -> bool {
+        self.inodestart <= block && block < self.size
+    }
+}
+
+impl FS {
+    // Check the header like recover_from_log in xv6 reads it
+    pub fn check_log(&self) -> Vec<FSError> {
+        let mut errors = vec![];
+        let log = match &self.log {
+            Some(log) => log,
+            None => return errors,
+        };
+        if log.n == 0 {
+            return errors;
+        }
+
+        let capacity = log.capacity(&self.geometry);
+        if log.n > capacity {
+            // The block list is garbage as well
+            errors.push(FSError::LogTooLong(log.n, capacity));
+            return errors;
+        }
+        for (i, block) in log.block.iter().take(log.n as usize).enumerate() {
+            if !self.geometry.is_loggable(*block) {
+                errors.push(FSError::InvalidLogBlock(i, *block));
+            }
+        }
+        if errors.is_empty() {
+            errors.push(FSError::CommittedLog(log.n));
+        }
+        errors
+    }
+}
@@ -0,0 +1,37 @@
+use super::*;
+use crate::fs::error::FSError;
+
+impl FS {
+    // The directory tree is traversed from the root, so it must be a directory whose "." and ".." refer to itself
+    // Missing or misplaced entries are reported by check_current_directory and check_parent_directory
+    pub fn check_root(&self) -> Vec<FSError> {
+        let mut errors = vec![];
+        let dinode = match self.dinodes.get(ROOTINO as usize) {
+            Some(dinode) => dinode,
+            None => {
+                errors.push(FSError::RootInodeOutOfRange(self.dinodes.len()));
+                return errors;
+            }
+        };
+        match dinode.typ {
+            FileType::DIR => {}
+            FileType::UNUSED => {
+                errors.push(FSError::UnusedRootInode);
+                return errors;
+            }
+            ref typ => {
+                errors.push(FSError::RootNotDirectory(format!("{:?}", typ)));
+                return errors;
+            }
+        }
+
+        for name in [".", ".."] {
+            if let Some(dirent) = self.find_dirent(ROOTINO, name) {
+                if dirent.inum != ROOTINO {
+                    errors.push(FSError::IncorrectRootDirRef(name.to_string(), dirent.inum));
+                }
+            }
+        }
+        errors
+    }
+}
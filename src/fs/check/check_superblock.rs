@@ -2,29 +2,72 @@ use super::*;
 use crate::fs::error::FSError;
 
 impl SuperBlock {
+    // Regions must be laid out in the order below without gaps or overlaps, and inside the image
+    // [ boot block | super block | log | inode blocks | free bit map | data blocks ]
     pub fn check_fields(&self) -> Vec<FSError> {
-        let err = vec![FSError::IncorrectSuperBlockField(self)];
-        if self.size != FSSIZE as u32 {
-            return err;
+        let mut errors = vec![];
+        let ninodeblocks = self.ninodes / IPB as u32 + 1;
+        let nbitmap = self.size / BPB as u32 + 1;
+        // u64 to avoid overflow with garbage fields
+        let size = self.size as u64;
+
+        // boot block and super block
+        let logstart = 2;
+        if self.logstart != logstart {
+            errors.push(FSError::IncorrectLogStart(self.logstart, logstart));
         }
-        if self.nblocks != NBLOCKS as u32 {
-            return err;
+        let logend = self.logstart as u64 + self.nlog as u64;
+        if logend > size {
+            errors.push(FSError::RegionOutOfImage("log", self.logstart, logend, self.size));
         }
-        if self.ninodes != NINODES as u32 {
-            return err;
+
+        if self.inodestart as u64 != logend {
+            errors.push(FSError::IncorrectInodeStart(self.inodestart, logend));
         }
-        if self.nlog != LOGSIZE as u32 {
-            return err;
+        let inodeend = self.inodestart as u64 + ninodeblocks as u64;
+        if inodeend > size {
+            errors.push(FSError::RegionOutOfImage("inode", self.inodestart, inodeend, self.size));
         }
-        if self.logstart != LOGSTART as u32 {
-            return err;
+
+        if self.bmapstart as u64 != inodeend {
+            errors.push(FSError::IncorrectBitmapStart(self.bmapstart, inodeend));
         }
-        if self.inodestart != INODESTART as u32 {
-            return err;
+        // The bitmap must have a bit for every block in the image
+        let bmapend = self.bmapstart as u64 + nbitmap as u64;
+        if bmapend > size {
+            errors.push(FSError::RegionOutOfImage("bitmap", self.bmapstart, bmapend, self.size));
+            return errors;
         }
-        if self.bmapstart != BITMAPSTART as u32 {
-            return err;
+
+        // Data blocks are all the remaining blocks
+        let nblocks = size - bmapend;
+        if self.nblocks as u64 != nblocks {
+            errors.push(FSError::IncorrectNBlocks(self.nblocks, nblocks));
         }
-        vec![]
+        // A misplaced bitmap may also run into the data region declared by nblocks
+        let datastart = size.saturating_sub(self.nblocks as u64);
+        if self.bmapstart as u64 != inodeend && bmapend > datastart {
+            errors.push(FSError::InsufficientBitmapRoom(self.bmapstart, nbitmap, datastart));
+        }
+        errors
+    }
+
+    // Whether the superblock matches the one mkfs writes for the profile
+    pub fn check_profile(&self, profile: &Profile) -> Vec<FSError> {
+        let expected = profile.superblock();
+        let fields = [
+            ("size", self.size, expected.size),
+            ("nblocks", self.nblocks, expected.nblocks),
+            ("ninodes", self.ninodes, expected.ninodes),
+            ("nlog", self.nlog, expected.nlog),
+            ("logstart", self.logstart, expected.logstart),
+            ("inodestart", self.inodestart, expected.inodestart),
+            ("bmapstart", self.bmapstart, expected.bmapstart),
+        ];
+        fields
+            .into_iter()
+            .filter(|(_, actual, expected)| actual != expected)
+            .map(|(field, actual, expected)| FSError::ProfileMismatch(field, actual, expected))
+            .collect()
     }
-}
\ No newline at end of file
+}
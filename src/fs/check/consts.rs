@@ -1,21 +1,41 @@
 use crate::fs::consts::*;
+use crate::fs::SuperBlock;
 
-/* For consistency check */
+/* Expected profile of the stock xv6 mkfs, for optional consistency check */
 // Size of file system image (blocks)
 pub const FSSIZE: usize = 2000;
 // Number of inodes
 pub const NINODES: usize = 200;
-
 // Number of log blocks
 pub const LOGSIZE: usize = 30;
-// Number of inode blocks
-pub const NINODEBLOCKS: usize = NINODES / IPB + 1;
-// Number of bitmap blocks
-pub const NBITMAP: usize = FSSIZE / BPB + 1;
-// Number of data blocks
-pub const NBLOCKS: usize = FSSIZE - LOGSIZE - NINODEBLOCKS - NBITMAP - 2;
 
-pub const LOGSTART: usize = 2;
-pub const INODESTART: usize = LOGSTART + LOGSIZE;
-pub const BITMAPSTART: usize = INODESTART + NINODEBLOCKS;
-pub const DATASTART: usize = BITMAPSTART + NBITMAP;
+// Parameters given to mkfs
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Profile {
+    // Size of file system image (blocks)
+    pub size: u32,
+    // Number of inodes
+    pub ninodes: u32,
+    // Number of log blocks
+    pub nlog: u32,
+}
+
+pub const XV6_PROFILE: Profile = Profile {
+    size: FSSIZE as u32,
+    ninodes: NINODES as u32,
+    nlog: LOGSIZE as u32,
+};
+
+impl Profile {
+    // Superblock written by mkfs for this profile
+    pub fn superblock(&self) -> SuperBlock {
+        let ninodeblocks = self.ninodes / IPB as u32 + 1;
+        let nbitmap = self.size / BPB as u32 + 1;
+        // boot block and super block
+        let nmeta = 2 + self.nlog + ninodeblocks + nbitmap;
+        let logstart = 2;
+        let inodestart = logstart + self.nlog;
+        let bmapstart = inodestart + ninodeblocks;
+        SuperBlock::new(FSMAGIC, self.size, self.size - nmeta, self.ninodes, self.nlog, logstart, inodestart, bmapstart)
+    }
+}
@@ -20,3 +20,7 @@ pub const BPB: usize = BSIZE * 8;
 pub const DIRSIZ: usize = 14;
 
 pub const DIRENTSIZE: usize = 2usize + DIRSIZ;
+
+// Offsets of "." and ".." in a directory, as mkfs and mkdir place them
+pub const CURRENT_DIR_OFFSET: usize = 0;
+pub const PARENT_DIR_OFFSET: usize = DIRENTSIZE;
@@ -1,54 +1,385 @@
 use super::*;
+use serde::Serialize;
 use thiserror::Error;
 
-// TODO: refine
-#[derive(Debug, Error)]
-pub enum FSError<'a> {
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Error, Serialize)]
+pub enum FSError {
     /* About superblock */
-    #[error("incorrect field value in superblock: {0:?}")]
-    IncorrectSuperBlockField(&'a SuperBlock),
+    // (logstart, expected logstart)
+    #[error("superblock has logstart {0}, but the log must start right after the superblock at block {1}")]
+    IncorrectLogStart(u32, u32),
+    // (inodestart, expected inodestart)
+    #[error("superblock has inodestart {0}, but inode blocks must start right after the log at block {1}")]
+    IncorrectInodeStart(u32, u64),
+    // (bmapstart, expected bmapstart)
+    #[error("superblock has bmapstart {0}, but bitmap blocks must start right after the inode blocks at block {1}")]
+    IncorrectBitmapStart(u32, u64),
+    // (bmapstart, number of bitmap blocks needed for size, first data block)
+    #[error("superblock has bmapstart {0}, but {1} bitmap blocks do not fit before the first data block {2}")]
+    InsufficientBitmapRoom(u32, u32, u64),
+    // (nblocks, expected nblocks)
+    #[error("superblock has nblocks {0}, but {1} blocks remain after the bitmap")]
+    IncorrectNBlocks(u32, u64),
+    // (name of region, first block, end of region, size)
+    #[error("{0} region spans blocks {1}..{2}, but the image has only {3} blocks")]
+    RegionOutOfImage(&'static str, u32, u64, u32),
+    // (field name, actual value, value in profile)
+    #[error("superblock has {0} {1}, but the profile expects {2}")]
+    ProfileMismatch(&'static str, u32, u32),
+
+    /* About log */
+    // (n in the log header, number of blocks the log can hold)
+    #[error("log header has n {0}, but the log can hold only {1} blocks")]
+    LogTooLong(u32, u32),
+    // (index in the log header, home block number)
+    #[error("log header has block {1} at index {0}, but logged blocks must be inode, bitmap or data blocks")]
+    InvalidLogBlock(usize, u32),
+    // (n in the log header)
+    #[error("log has {0} committed blocks, which xv6 installs on the next boot")]
+    CommittedLog(u32),
 
     /* About block usage */
-    // (data block number, status of block)
-    #[error("bitmap assumes data block {0} is {1:?}, but this is incorrect")]
-    IncorrectBitmap(u32, &'a BlockStatus),
-    // (data block number)
-    #[error("{0}-th data block is referenced from multiple inodes")]
-    MultipleRef(u32),
+    // (block number, status of block)
+    #[error("bitmap assumes block {0} is {1:?}, but this is incorrect")]
+    IncorrectBitmap(BlockNo, BlockStatus),
+    // (name of region, block number)
+    #[error("bitmap assumes {0} block {1} is Free, but metadata blocks must be Allocated")]
+    FreeMetadataBlock(&'static str, BlockNo),
+    // Bits for blocks beyond the size of the image
+    // (first block number, number of allocated bits)
+    #[error("bitmap has {1} Allocated bits from block {0}, but blocks beyond the image must be Free")]
+    AllocatedPadding(BlockNo, usize),
+    // (block number, references to the block)
+    #[error("block {0} is referenced multiple times: by {}", join_refs(.1))]
+    MultipleRef(BlockNo, Vec<BlockRef>),
 
     /* About inode */
+    // (inode number, type on disk)
+    #[error("{0}-th inode has invalid file type {1}")]
+    InvalidFileType(u16, u16),
+    // Such addresses are dropped by lenient parsing, so that other checks skip them
+    // (reference, block number)
+    #[error("block {1} is referenced by {0}, but it is outside the data region")]
+    BlockOutOfRange(BlockRef, u32),
     // (inode number)
     #[error("{0}-th inode is a device file, but its major/minor number is invalid")]
     InvalidDevice(u16),
     // Note that in the case of directories, references by "." is not counted
-    // (inode number, nlink)
-    #[error("{0}-th inode assumes nlink is {1}, but this is incorrect")]
-    IncorrectNLink(u16, u16),
-    // (inode number)
-    #[error("{0}-th inode refers to a freed data block")]
-    InvalidDataBlockRef(u16),
-    // Must be ceil(dinode.size/BSIZE)
-    // (inode number, number of valid references in addrs)
-    #[error("{0}-th inode has size {1} and refers to {2} data blocks, but this is invalid")]
-    InvalidNumberOfDataBlockRef(u16, u32, usize),
+    // (inode number, nlink, number of references)
+    #[error("{0}-th inode assumes nlink is {1}, but it is referenced {2} times")]
+    IncorrectNLink(u16, u16, u16),
+    // (reference, block number)
+    #[error("block {1} is referenced by {0}, but it is Free in the bitmap")]
+    InvalidDataBlockRef(BlockRef, BlockNo),
+    // (inode number, size)
+    #[error("{0}-th inode has size {1}, but a file can have at most MAXFILE * BSIZE bytes")]
+    FileTooLarge(u16, u32),
+    // (inode number, size)
+    #[error("{0}-th inode of directory has size {1}, but it must be a multiple of DIRENTSIZE")]
+    InvalidDirectorySize(u16, u32),
+    // xv6 never makes holes, but bmap allocates the block when it is read or written
+    // (inode number, number of holes)
+    #[error("{0}-th inode has {1} blocks without a data block within its size")]
+    MissingBlocks(u16, usize),
+    // (inode number, block number in the file, block number on disk)
+    #[error("{0}-th inode refers to block {2} as block {1} of the file, but it is beyond the size")]
+    BlockBeyondSize(u16, usize, BlockNo),
+    // (inode number, block number)
+    #[error("{0}-th inode refers to block {1} as its indirect block, but the size fits in direct blocks")]
+    IndirectBlockBeyondSize(u16, BlockNo),
+    // Left by an itrunc which did not complete
+    // (inode number, fields which are not zero, number of stale references to data blocks)
+    #[error("{0}-th inode is unused, but has non-zero {}, with {2} stale references to data blocks", .1.join(", "))]
+    HalfFreedInode(u16, Vec<&'static str>, usize),
 
     /* About directory */
+    // (inode number of directory, entry index, name, inode number)
+    #[error("{0}-th inode of directory refers to the unused {3}-th inode by entry {1} \"{2}\"")]
+    InvalidInodeRef(u16, usize, String, u16),
+    // (inode number of directory, entry index, name, inode number)
+    #[error("{0}-th inode of directory refers to {3}-th inode by entry {1} \"{2}\", but there is no such inode")]
+    InodeOutOfRange(u16, usize, String, u16),
     // (inode number of directory)
-    #[error("{0}-th inode of directory refers to an unused inode")]
-    InvalidInodeRef(u16),
+    #[error("{0}-th inode of directory has no \".\" entry")]
+    MissingCurrentDirEntry(u16),
+    // "." must be the first entry, as mkfs and mkdir place it
+    // (inode number of directory, offset of ".")
+    #[error("{0}-th inode of directory has \".\" at offset {1}, but it must be at offset 0")]
+    MisplacedCurrentDirEntry(u16, usize),
+    // (inode number of directory, inode number referred by ".")
+    #[error("{0}-th inode of directory refers to {1}-th inode by \".\", but it must refer to itself")]
+    IncorrectCurrentDirRef(u16, u16),
     // (inode number of directory)
-    #[error("{0}-th inode of directory does not refer to itself by \".\"")]
-    IncorrectCurrentDirRef(u16),
+    #[error("{0}-th inode of directory has no \"..\" entry")]
+    MissingParentDirEntry(u16),
+    // ".." must be the second entry, as isdirempty skips the first two entries
+    // (inode number of directory, offset of "..")
+    #[error("{0}-th inode of directory has \"..\" at offset {1}, but it must be at offset 16")]
+    MisplacedParentDirEntry(u16, usize),
     // Note that for "/", it refers to itself
-    // (inode number of directory)
-    #[error("{0}-th inode of directory does not refer to parent directory by \"..\"")]
-    IncorrectParentDirRef(u16),
-    // Must be referenced only by itself, its parent directories, and subdirectories
-    // (inode number of directory)
-    #[error("{0}-th inode of directory is falsely referenced by other directories")]
-    InvalidDirRef(u16),
+    // (inode number of directory, inode number referred by "..", inode number of parent)
+    #[error("{0}-th inode of directory refers to {1}-th inode by \"..\", but its parent directory is {2}-th inode")]
+    IncorrectParentDirRef(u16, u16, u16),
+    // (inode number of directory, entry index)
+    #[error("{0}-th inode of directory has entry {1} with an empty name")]
+    EmptyDirentName(u16, usize),
+    // (inode number of directory, entry index, name)
+    #[error("{0}-th inode of directory has entry {1} named {2:?}, but names cannot contain '/'")]
+    SlashInDirentName(u16, usize, String),
+    // Invalid UTF-8 is shown as U+FFFD
+    // (inode number of directory, entry index, name)
+    #[error("{0}-th inode of directory has entry {1} named {2:?}, which contains non-printable characters")]
+    NonPrintableDirentName(u16, usize, String),
+    // (inode number of directory, entry index, name)
+    #[error("{0}-th inode of directory has entry {1} named {2:?}, but the bytes after its terminating NUL are not zero")]
+    GarbageAfterDirentName(u16, usize, String),
+    // Entry with the same name as an earlier one in the directory, which namei never finds
+    // (inode number of directory, name, offset of earlier entry, its inode number, offset of later entry, its inode number)
+    #[error("{0}-th inode of directory has entries named {1:?} at offsets {2} and {4}, referring to {3}-th and {5}-th inodes")]
+    DuplicateDirentName(u16, String, usize, u16, usize, u16),
+    // Entry in a directory unreachable from the root, which links a directory in the tree
+    // (inode number of directory, entry index, name, inode number of linked directory)
+    #[error("{0}-th inode of directory refers to {3}-th inode of directory by entry {1} \"{2}\", which is already linked from the directory tree")]
+    InvalidDirRef(u16, usize, String, u16),
+    // (inode number of directory, entry index, inode number of ancestor, path of the entry)
+    #[error("{3} refers to {2}-th inode, which is its ancestor directory")]
+    DirectoryLoop(u16, usize, u16, String),
+    // (inode number of directory, entry index, inode number of linked directory, path of the entry)
+    #[error("{3} refers to {2}-th inode of directory, which is already linked from another entry")]
+    DirectoryHardLink(u16, usize, u16, String),
 
     /* Others */
     #[error("{0}-th inode cannot be traced from the root directory")]
     DanglingInode(u16),
-}
\ No newline at end of file
+    // (number of inodes)
+    #[error("root inode does not exist, as there are only {0} inodes")]
+    RootInodeOutOfRange(usize),
+    #[error("root inode is not allocated")]
+    UnusedRootInode,
+    // (type of root inode)
+    #[error("root inode is {0}, but it must be a directory")]
+    RootNotDirectory(String),
+    // (name of entry, inode number referred by the entry)
+    #[error("root directory refers to {1}-th inode by {0:?}, but it must refer to itself")]
+    IncorrectRootDirRef(String, u16),
+}
+
+fn join_refs(refs: &[BlockRef]) -> String {
+    refs.iter().map(|r| r.to_string()).collect::<Vec<_>>().join(", ")
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    // Suspicious, but xv6 can still use the file system
+    Warning,
+    Error,
+}
+
+// Where an error was found
+#[derive(Debug, Clone, Default, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+pub struct Location {
+    // Inode number
+    pub inum: Option<u16>,
+    // Block number on disk
+    pub block: Option<u32>,
+    // Path from the root directory, if the inode is reachable
+    pub path: Option<String>,
+    // Index of the entry in the directory
+    pub dirent: Option<usize>,
+}
+
+// Owned record of an FSError, which outlives FS
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+pub struct Finding {
+    pub check: &'static str,
+    pub severity: Severity,
+    pub location: Location,
+    pub observed: String,
+    pub expected: Option<String>,
+    pub message: String,
+}
+
+impl FSError {
+    // Stable identifier of the check which found the error
+    pub fn check_id(&self) -> &'static str {
+        match self {
+            FSError::IncorrectLogStart(..) => "incorrect_log_start",
+            FSError::IncorrectInodeStart(..) => "incorrect_inode_start",
+            FSError::IncorrectBitmapStart(..) => "incorrect_bitmap_start",
+            FSError::InsufficientBitmapRoom(..) => "insufficient_bitmap_room",
+            FSError::IncorrectNBlocks(..) => "incorrect_nblocks",
+            FSError::RegionOutOfImage(..) => "region_out_of_image",
+            FSError::ProfileMismatch(..) => "profile_mismatch",
+            FSError::IncorrectBitmap(..) => "incorrect_bitmap",
+            FSError::LogTooLong(..) => "log_too_long",
+            FSError::InvalidLogBlock(..) => "invalid_log_block",
+            FSError::CommittedLog(..) => "committed_log",
+            FSError::FreeMetadataBlock(..) => "free_metadata_block",
+            FSError::AllocatedPadding(..) => "allocated_padding",
+            FSError::MultipleRef(..) => "multiple_ref",
+            FSError::InvalidFileType(..) => "invalid_file_type",
+            FSError::BlockOutOfRange(..) => "block_out_of_range",
+            FSError::InvalidDevice(..) => "invalid_device",
+            FSError::IncorrectNLink(..) => "incorrect_nlink",
+            FSError::InvalidDataBlockRef(..) => "invalid_data_block_ref",
+            FSError::FileTooLarge(..) => "file_too_large",
+            FSError::InvalidDirectorySize(..) => "invalid_directory_size",
+            FSError::MissingBlocks(..) => "missing_blocks",
+            FSError::BlockBeyondSize(..) => "block_beyond_size",
+            FSError::IndirectBlockBeyondSize(..) => "indirect_block_beyond_size",
+            FSError::HalfFreedInode(..) => "half_freed_inode",
+            FSError::InvalidInodeRef(..) => "invalid_inode_ref",
+            FSError::InodeOutOfRange(..) => "inode_out_of_range",
+            FSError::MissingCurrentDirEntry(..) => "missing_current_dir_entry",
+            FSError::MisplacedCurrentDirEntry(..) => "misplaced_current_dir_entry",
+            FSError::IncorrectCurrentDirRef(..) => "incorrect_current_dir_ref",
+            FSError::MissingParentDirEntry(..) => "missing_parent_dir_entry",
+            FSError::MisplacedParentDirEntry(..) => "misplaced_parent_dir_entry",
+            FSError::IncorrectParentDirRef(..) => "incorrect_parent_dir_ref",
+            FSError::InvalidDirRef(..) => "invalid_dir_ref",
+            FSError::DirectoryLoop(..) => "directory_loop",
+            FSError::DirectoryHardLink(..) => "directory_hard_link",
+            FSError::EmptyDirentName(..) => "empty_dirent_name",
+            FSError::SlashInDirentName(..) => "slash_in_dirent_name",
+            FSError::NonPrintableDirentName(..) => "non_printable_dirent_name",
+            FSError::GarbageAfterDirentName(..) => "garbage_after_dirent_name",
+            FSError::DuplicateDirentName(..) => "duplicate_dirent_name",
+            FSError::DanglingInode(..) => "dangling_inode",
+            FSError::RootInodeOutOfRange(..) => "root_inode_out_of_range",
+            FSError::UnusedRootInode => "unused_root_inode",
+            FSError::RootNotDirectory(..) => "root_not_directory",
+            FSError::IncorrectRootDirRef(..) => "incorrect_root_dir_ref",
+        }
+    }
+
+    pub fn severity(&self) -> Severity {
+        match self {
+            // A different mkfs configuration is not an error by itself
+            FSError::ProfileMismatch(..) => Severity::Warning,
+            // The log is consistent, and the kernel replays it
+            FSError::CommittedLog(..) => Severity::Warning,
+            // The validation of device numbers is heuristic
+            FSError::InvalidDevice(..) => Severity::Warning,
+            // namei can still find such entries
+            FSError::NonPrintableDirentName(..) | FSError::GarbageAfterDirentName(..) => Severity::Warning,
+            // The kernel fills holes on demand
+            FSError::MissingBlocks(..) => Severity::Warning,
+            _ => Severity::Error,
+        }
+    }
+}
+
+impl FS {
+    fn locate_inode(&self, inum: u16) -> Location {
+        Location {
+            inum: Some(inum),
+            path: self.get_path(&inum),
+            ..Default::default()
+        }
+    }
+
+    fn locate_block(&self, block: u32) -> Location {
+        Location {
+            block: Some(block),
+            ..Default::default()
+        }
+    }
+
+    // Resolve the location and the observed/expected values of the error
+    pub fn finding(&self, error: &FSError) -> Finding {
+        let data_region = format!("{}..{}", self.geometry.datastart, self.geometry.size);
+        let (location, observed, expected) = match error {
+            FSError::IncorrectLogStart(actual, expected) => (self.locate_block(1), actual.to_string(), Some(expected.to_string())),
+            FSError::IncorrectInodeStart(actual, expected) => (self.locate_block(1), actual.to_string(), Some(expected.to_string())),
+            FSError::IncorrectBitmapStart(actual, expected) => (self.locate_block(1), actual.to_string(), Some(expected.to_string())),
+            FSError::InsufficientBitmapRoom(actual, nbitmap, datastart) => {
+                let expected = (*datastart).checked_sub(*nbitmap as u64).map(|x| format!("0..={}", x));
+                (self.locate_block(1), actual.to_string(), expected)
+            }
+            FSError::IncorrectNBlocks(actual, expected) => (self.locate_block(1), actual.to_string(), Some(expected.to_string())),
+            FSError::RegionOutOfImage(_, _, end, size) => (self.locate_block(1), end.to_string(), Some(format!("0..={}", size))),
+            FSError::ProfileMismatch(_, actual, expected) => (self.locate_block(1), actual.to_string(), Some(expected.to_string())),
+            FSError::IncorrectBitmap(addr, status) => {
+                let expected = match status {
+                    BlockStatus::Free => BlockStatus::Allocated,
+                    BlockStatus::Allocated => BlockStatus::Free,
+                };
+                (self.locate_block(addr.0), format!("{:?}", status), Some(format!("{:?}", expected)))
+            }
+            FSError::LogTooLong(n, capacity) => (self.locate_block(self.geometry.logstart), n.to_string(), Some(format!("0..={}", capacity))),
+            FSError::InvalidLogBlock(_, block) => {
+                (self.locate_block(self.geometry.logstart), block.to_string(), Some(format!("{}..{}", self.geometry.inodestart, self.geometry.size)))
+            }
+            FSError::CommittedLog(n) => (self.locate_block(self.geometry.logstart), n.to_string(), Some("0".to_string())),
+            FSError::FreeMetadataBlock(_, addr) => (self.locate_block(addr.0), "Free".to_string(), Some("Allocated".to_string())),
+            FSError::AllocatedPadding(addr, n) => (self.locate_block(addr.0), format!("{} Allocated bits", n), Some("Free".to_string())),
+            FSError::MultipleRef(addr, refs) => (self.locate_block(addr.0), join_refs(refs), Some("at most 1 reference".to_string())),
+            FSError::InvalidFileType(inum, typ) => (self.locate_inode(*inum), typ.to_string(), Some("0..=3".to_string())),
+            FSError::BlockOutOfRange(r, block) => (Location { block: Some(*block), ..self.locate_inode(r.inum()) }, block.to_string(), Some(data_region)),
+            FSError::InvalidDevice(inum) => {
+                let dinode = &self.dinodes[*inum as usize];
+                (self.locate_inode(*inum), format!("{}/{}", dinode.major, dinode.minor), None)
+            }
+            FSError::IncorrectNLink(inum, nlink, count) => (self.locate_inode(*inum), nlink.to_string(), Some(count.to_string())),
+            FSError::InvalidDataBlockRef(r, addr) => (Location { block: Some(addr.0), ..self.locate_inode(r.inum()) }, "Free".to_string(), Some("Allocated".to_string())),
+            FSError::FileTooLarge(inum, size) => (self.locate_inode(*inum), size.to_string(), Some(format!("0..={}", MAXFILE * BSIZE))),
+            FSError::InvalidDirectorySize(inum, size) => (self.locate_inode(*inum), size.to_string(), Some(format!("multiple of {}", DIRENTSIZE))),
+            FSError::MissingBlocks(inum, holes) => (self.locate_inode(*inum), format!("{} holes", holes), Some("no holes".to_string())),
+            FSError::BlockBeyondSize(inum, _, addr) => (Location { block: Some(addr.0), ..self.locate_inode(*inum) }, addr.to_string(), Some("0".to_string())),
+            FSError::HalfFreedInode(inum, fields, _) => (self.locate_inode(*inum), fields.join(", "), Some("0".to_string())),
+            FSError::IndirectBlockBeyondSize(inum, addr) => (Location { block: Some(addr.0), ..self.locate_inode(*inum) }, addr.to_string(), Some("0".to_string())),
+            FSError::InvalidInodeRef(dir, index, _, inum) => (Location { dirent: Some(*index), ..self.locate_inode(*dir) }, format!("unused inode {}", inum), Some("allocated inode".to_string())),
+            FSError::InodeOutOfRange(dir, index, _, inum) => {
+                (Location { dirent: Some(*index), ..self.locate_inode(*dir) }, inum.to_string(), Some(format!("1..{}", self.dinodes.len())))
+            }
+            FSError::MissingCurrentDirEntry(inum) => (self.locate_inode(*inum), "missing".to_string(), Some("\".\" at offset 0".to_string())),
+            FSError::MisplacedCurrentDirEntry(inum, offset) => {
+                (Location { dirent: Some(offset / DIRENTSIZE), ..self.locate_inode(*inum) }, offset.to_string(), Some(CURRENT_DIR_OFFSET.to_string()))
+            }
+            FSError::IncorrectCurrentDirRef(inum, found) => (self.locate_inode(*inum), found.to_string(), Some(inum.to_string())),
+            FSError::MissingParentDirEntry(inum) => (self.locate_inode(*inum), "missing".to_string(), Some("\"..\" at offset 16".to_string())),
+            FSError::MisplacedParentDirEntry(inum, offset) => {
+                (Location { dirent: Some(offset / DIRENTSIZE), ..self.locate_inode(*inum) }, offset.to_string(), Some(PARENT_DIR_OFFSET.to_string()))
+            }
+            FSError::IncorrectParentDirRef(inum, found, parent) => (self.locate_inode(*inum), found.to_string(), Some(parent.to_string())),
+            FSError::InvalidDirRef(dir, index, _, inum) => {
+                (Location { dirent: Some(*index), ..self.locate_inode(*dir) }, format!("extra link to directory {}", inum), Some("1 link to each directory".to_string()))
+            }
+            FSError::DirectoryLoop(dir, index, inum, path) => {
+                (Location { dirent: Some(*index), path: Some(path.clone()), ..self.locate_inode(*dir) }, format!("link to ancestor {}", inum), Some("no link to an ancestor".to_string()))
+            }
+            FSError::DirectoryHardLink(dir, index, inum, path) => {
+                (Location { dirent: Some(*index), path: Some(path.clone()), ..self.locate_inode(*dir) }, format!("extra link to directory {}", inum), Some("1 link to each directory".to_string()))
+            }
+            FSError::EmptyDirentName(dir, index) => (Location { dirent: Some(*index), ..self.locate_inode(*dir) }, "\"\"".to_string(), Some("non-empty name".to_string())),
+            FSError::SlashInDirentName(dir, index, name) => (Location { dirent: Some(*index), ..self.locate_inode(*dir) }, format!("{:?}", name), Some("name without '/'".to_string())),
+            FSError::NonPrintableDirentName(dir, index, name) => (Location { dirent: Some(*index), ..self.locate_inode(*dir) }, format!("{:?}", name), Some("printable ASCII".to_string())),
+            FSError::GarbageAfterDirentName(dir, index, name) => {
+                let dirent = self.get_dirents(dir).and_then(|dirents| dirents.iter().find(|dirent| dirent.index() == *index));
+                let raw = dirent.map_or(format!("{:?}", name), |dirent| format!("\"{}\"", dirent.raw_name.escape_ascii()));
+                (Location { dirent: Some(*index), ..self.locate_inode(*dir) }, raw, Some("NUL padding".to_string()))
+            }
+            FSError::DuplicateDirentName(dir, name, first, _, offset, _) => {
+                let found = format!("{:?} as entry {}", name, first / DIRENTSIZE);
+                (Location { dirent: Some(offset / DIRENTSIZE), ..self.locate_inode(*dir) }, found, Some("unique name".to_string()))
+            }
+            FSError::DanglingInode(inum) => (self.locate_inode(*inum), "unreachable".to_string(), Some("reachable from /".to_string())),
+            FSError::RootInodeOutOfRange(ninodes) => (Location::default(), ninodes.to_string(), Some(format!("more than {}", ROOTINO))),
+            FSError::UnusedRootInode => (self.locate_inode(ROOTINO), "UNUSED".to_string(), Some("DIR".to_string())),
+            FSError::RootNotDirectory(typ) => (self.locate_inode(ROOTINO), typ.clone(), Some("DIR".to_string())),
+            FSError::IncorrectRootDirRef(name, inum) => {
+                let index = self.find_dirent(ROOTINO, name).map(|dirent| dirent.index());
+                (Location { dirent: index, ..self.locate_inode(ROOTINO) }, inum.to_string(), Some(ROOTINO.to_string()))
+            }
+        };
+        Finding {
+            check: error.check_id(),
+            severity: error.severity(),
+            location,
+            observed,
+            expected,
+            message: error.to_string(),
+        }
+    }
+}
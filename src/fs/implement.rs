@@ -1,7 +1,9 @@
 use super::*;
 
 use std::collections;
+use crate::fs::error::FSError;
 use crate::parser;
+use crate::parser::ParseMode;
 use crate::util::graph::Node;
 
 impl BlockStatus {
@@ -15,6 +17,7 @@ impl BlockStatus {
 }
 
 impl SuperBlock {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(magic: u32, size: u32, nblocks: u32, ninodes: u32, nlog: u32, logstart: u32, inodestart: u32, bmapstart: u32) -> Self {
         SuperBlock {
             magic,
@@ -29,8 +32,95 @@ impl SuperBlock {
     }
 }
 
+impl BlockRef {
+    pub fn inum(&self) -> u16 {
+        match self {
+            BlockRef::IndirectBlock { inum } | BlockRef::Direct { inum, .. } | BlockRef::Indirect { inum, .. } => *inum,
+        }
+    }
+}
+
+impl std::fmt::Display for BlockRef {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BlockRef::IndirectBlock { inum } => write!(f, "{}-th inode as its indirect block", inum),
+            BlockRef::Direct { inum, slot } => write!(f, "{}-th inode by addrs[{}]", inum, slot),
+            BlockRef::Indirect { inum, slot } => write!(f, "{}-th inode by entry {} of its indirect block", inum, slot),
+        }
+    }
+}
+
+impl LogHeader {
+    pub fn new(n: u32, block: Vec<u32>) -> Self {
+        LogHeader {
+            n,
+            block,
+        }
+    }
+}
+
+impl Geometry {
+    // Assume that sb.nblocks <= sb.size
+    pub fn new(sb: &SuperBlock) -> Self {
+        Geometry {
+            size: sb.size,
+            nlog: sb.nlog,
+            logstart: sb.logstart,
+            ninodes: sb.ninodes,
+            ninodeblocks: sb.ninodes / IPB as u32 + 1,
+            inodestart: sb.inodestart,
+            nbitmap: sb.size / BPB as u32 + 1,
+            bmapstart: sb.bmapstart,
+            nblocks: sb.nblocks,
+            datastart: sb.size - sb.nblocks,
+        }
+    }
+
+    // Whether the block number on disk is inside the data region
+    pub fn in_data_region(&self, block: u32) -> bool {
+        self.datastart <= block && block < self.size
+    }
+
+    // Name of the region the block belongs to, as in FSError::RegionOutOfImage
+    pub fn region(&self, block: BlockNo) -> &'static str {
+        let inside = |start: u32, n: u32| start <= block.0 && (block.0 as u64) < start as u64 + n as u64;
+        match block.0 {
+            0 => "boot",
+            1 => "superblock",
+            _ if inside(self.logstart, self.nlog) => "log",
+            _ if inside(self.inodestart, self.ninodeblocks) => "inode",
+            _ if inside(self.bmapstart, self.nbitmap) => "bitmap",
+            _ if self.in_data_region(block.0) => "data",
+            _ => "unknown",
+        }
+    }
+
+    // Assume that the block is inside the data region
+    pub fn data_index(&self, block: BlockNo) -> DataIndex {
+        debug_assert!(self.in_data_region(block.0));
+        DataIndex(block.0 - self.datastart)
+    }
+
+    // First block after the bitmap
+    // A broken superblock may put data blocks before it, which must never be written
+    pub fn metadata_end(&self) -> u32 {
+        self.bmapstart.saturating_add(self.nbitmap)
+    }
+
+    // Every block in the data region
+    pub fn data_blocks(&self) -> impl Iterator<Item = BlockNo> {
+        (self.datastart..self.size).map(BlockNo)
+    }
+}
+
+impl std::fmt::Display for BlockNo {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
 impl Dinode {
-    pub fn new(typ: FileType, major: u16, minor: u16, nlink: u16, size: u32, addrs: [Option<u32>; NDIRECT + 1]) -> Self {
+    pub fn new(typ: FileType, major: u16, minor: u16, nlink: u16, size: u32, addrs: [Option<BlockNo>; NDIRECT + 1], raw_addrs: [u32; NDIRECT + 1]) -> Self {
         Dinode {
             typ,
             major,
@@ -38,109 +128,356 @@ impl Dinode {
             nlink,
             size,
             addrs,
+            raw_addrs,
         }
     }
 }
 
 impl Dirent {
-    pub fn new(inum: u16, name: String) -> Self {
+    pub fn new(inum: u16, raw_name: [u8; DIRSIZ], offset: usize) -> Self {
+        let len = raw_name.iter().position(|c| *c == 0).unwrap_or(DIRSIZ);
         Dirent {
             inum,
-            name,
+            raw_name,
+            name: String::from_utf8_lossy(&raw_name[..len]).into_owned(),
+            offset,
         }
     }
+
+    // Index of the entry in the directory
+    pub fn index(&self) -> usize {
+        self.offset / DIRENTSIZE
+    }
+
+    // Name up to the first NUL, which namecmp in xv6 compares
+    pub fn name_bytes(&self) -> &[u8] {
+        let len = self.raw_name.iter().position(|c| *c == 0).unwrap_or(DIRSIZ);
+        &self.raw_name[..len]
+    }
 }
 
 impl FS {
-    pub fn new(superblock: SuperBlock, dinodes: Vec<Dinode>, bitmap: Vec<BlockStatus>, data: Vec<Vec<u8>>) -> Self {
+    pub fn new(superblock: SuperBlock, log: Option<LogHeader>, dinodes: Vec<Dinode>, bitmap: Vec<BlockStatus>, data: Vec<Vec<u8>>) -> Self {
         let mut fs = FS {
+            geometry: Geometry::new(&superblock),
             superblock,
+            log,
             dinodes,
             bitmap,
             data,
             inum_to_dirents: collections::HashMap::new(),
             directory_tree: Rc::new(Node::new(ROOTINO)),
             inum_to_node: collections::HashMap::new(),
+            tree_errors: vec![],
         };
         fs.init();
         fs
     }
 
     pub fn get_dirents(&self, inum: &u16) -> Option<&Vec<Dirent>> {
-        match self.inum_to_dirents.get(inum).unwrap() {
-            Some(dirents) => Some(dirents),
-            None => None,
-        }
+        // None also for inode numbers out of range
+        self.inum_to_dirents.get(inum)?.as_ref()
+    }
+
+    // Content of a data block
+    pub fn block(&self, block: BlockNo) -> &[u8] {
+        &self.data[self.geometry.data_index(block).0 as usize]
+    }
+
+    pub fn block_mut(&mut self, block: BlockNo) -> &mut [u8] {
+        let index = self.geometry.data_index(block);
+        &mut self.data[index.0 as usize]
+    }
+
+    pub fn block_status(&self, block: BlockNo) -> BlockStatus {
+        self.bitmap[block.0 as usize]
     }
 
     pub fn get_node(&self, inum: &u16) -> Option<&Rc<Node<u16>>> {
         self.inum_to_node.get(inum)
     }
 
+    // Path from the root directory, following the first parent of each node
+    pub fn get_path(&self, inum: &u16) -> Option<String> {
+        let mut names = vec![];
+        let mut node = Rc::clone(self.get_node(inum)?);
+        // Bounded by the number of inodes in case of a broken tree
+        for _ in 0..self.dinodes.len() {
+            if node.value == ROOTINO {
+                names.reverse();
+                return Some(format!("/{}", names.join("/")));
+            }
+            let parent = node.parents.borrow().first()?.upgrade()?;
+            let dirent = self
+                .get_dirents(&parent.value)?
+                .iter()
+                .find(|dirent| dirent.inum == node.value && dirent.name != "." && dirent.name != "..")?;
+            names.push(dirent.name.clone());
+            node = parent;
+        }
+        None
+    }
+
     fn init(&mut self) {
         self.init_dirents_hashmap();
         self.construct_directory_tree();
     }
 
+    // Rebuild the directory tree after dinodes or data are modified
+    pub(crate) fn reload(&mut self) {
+        self.inum_to_dirents.clear();
+        self.inum_to_node.clear();
+        self.tree_errors.clear();
+        self.directory_tree = Rc::new(Node::new(ROOTINO));
+        self.init();
+    }
+
     fn init_dirents_hashmap(&mut self) {
-        let map = &mut self.inum_to_dirents;
+        let mut map = collections::HashMap::new();
         for (i, dinode) in self.dinodes.iter().enumerate() {
             if dinode.typ != FileType::DIR {
                 map.insert(i as u16, None);
                 continue;
             }
-            for v in dinode.addrs.iter() {
-                if let Some(addr) = v {
-                    let dirents = parser::parse_dirents(&self.data[*addr as usize]);
-                    let dirents: Vec<Dirent> = dirents.into_iter().filter_map(|x| x).collect();
-                    map.insert(i as u16, Some(dirents));
+            let mut dirents = vec![];
+            for (fbn, addr) in self.get_file_blocks(dinode).into_iter().enumerate() {
+                if let Some(addr) = addr {
+                    let start = fbn * BSIZE;
+                    let len = (dinode.size as usize - start).min(BSIZE);
+                    dirents.extend(parser::parse_dirents(&self.block(addr)[..len], start).into_iter().flatten());
                 }
             }
+            map.insert(i as u16, Some(dirents));
         }
+        self.inum_to_dirents = map;
     }
 
+    // Each inode has a single node, so hard links to a file give the node multiple parents
+    // A directory is linked from the entry in the directory its ".." names, like pass 3 of e2fsck,
+    // or else from the first entry found in BFS order, and other entries are recorded in tree_errors
     fn construct_directory_tree(&mut self) {
         let root = &mut self.directory_tree;
         self.inum_to_node.insert(ROOTINO, Rc::clone(root));
         Node::add_parent(root, root);
 
+        // ".." of each directory, and (directory, inode number) of entries other than "." and ".."
+        let mut dotdot = collections::HashMap::new();
+        let mut linked = collections::HashSet::new();
+        for (inum, dirents) in self.inum_to_dirents.iter() {
+            for dirent in dirents.iter().flatten() {
+                match dirent.name.as_str() {
+                    "." => {}
+                    ".." => {
+                        dotdot.entry(*inum).or_insert(dirent.inum);
+                    }
+                    _ => {
+                        linked.insert((*inum, dirent.inum));
+                    }
+                }
+            }
+        }
+
+        // (directory, entry index, inode number, whether it is an ancestor)
+        let mut extra_links = vec![];
+        // Entries to a directory whose ".." names another directory linking it: (directory, entry index, inode number)
+        let mut deferred = collections::VecDeque::new();
         let q = &mut collections::VecDeque::new();
-        q.push_back(Rc::clone(&root));
-        while let Some(node) = q.pop_front() {
-            let inum = node.value;
-            self.inum_to_node.insert(inum, Rc::clone(&node));
-
-            if let Some(dirents) = self.get_dirents(&inum) {
-                for dirent in dirents {
-                    if dirent.name == "." || dirent.name == ".." {
-                        continue;
+        q.push_back(Rc::clone(root));
+        loop {
+            while let Some(node) = q.pop_front() {
+                let inum = node.value;
+                // Fields are borrowed separately so that inum_to_node can be updated
+                if let Some(Some(dirents)) = self.inum_to_dirents.get(&inum) {
+                    for dirent in dirents {
+                        // Entries to inodes out of range are reported by check_dirents
+                        if dirent.name == "." || dirent.name == ".." || dirent.inum as usize >= self.dinodes.len() {
+                            continue;
+                        }
+                        let is_dir = self.dinodes[dirent.inum as usize].typ == FileType::DIR;
+                        match self.inum_to_node.get(&dirent.inum) {
+                            Some(child) if is_dir => {
+                                let ancestor = Self::is_ancestor(child, &node);
+                                extra_links.push((inum, dirent.index(), dirent.inum, ancestor));
+                            }
+                            Some(child) => Node::set_relation(&node, child),
+                            None if is_dir
+                                && dotdot
+                                    .get(&dirent.inum)
+                                    .is_some_and(|parent| *parent != inum && linked.contains(&(*parent, dirent.inum))) =>
+                            {
+                                deferred.push_back((Rc::clone(&node), dirent.index(), dirent.inum));
+                            }
+                            None => {
+                                let child = Rc::new(Node::new(dirent.inum));
+                                Node::set_relation(&node, &child);
+                                self.inum_to_node.insert(dirent.inum, Rc::clone(&child));
+                                q.push_back(child);
+                            }
+                        }
                     }
-                    let child = Rc::new(Node::new(dirent.inum));
+                }
+            }
+
+            // The directory named by ".." was unreachable, so fall back to the deferred entry
+            let (node, index, inum) = match deferred.pop_front() {
+                Some(entry) => entry,
+                None => break,
+            };
+            match self.inum_to_node.get(&inum) {
+                Some(child) => {
+                    let ancestor = Self::is_ancestor(child, &node);
+                    extra_links.push((node.value, index, inum, ancestor));
+                }
+                None => {
+                    let child = Rc::new(Node::new(inum));
                     Node::set_relation(&node, &child);
-                    q.push_back(Rc::clone(&child));
+                    self.inum_to_node.insert(inum, Rc::clone(&child));
+                    q.push_back(child);
                 }
             }
         }
+
+        self.tree_errors = extra_links
+            .into_iter()
+            .map(|(dir, index, inum, ancestor)| {
+                let name = &self.get_dirents(&dir).unwrap().iter().find(|dirent| dirent.index() == index).unwrap().name;
+                let path = match self.get_path(&dir) {
+                    Some(path) if path == "/" => format!("/{}", name),
+                    Some(path) => format!("{}/{}", path, name),
+                    None => name.clone(),
+                };
+                if ancestor {
+                    FSError::DirectoryLoop(dir, index, inum, path)
+                } else {
+                    FSError::DirectoryHardLink(dir, index, inum, path)
+                }
+            })
+            .collect();
     }
 
-    pub fn get_all_addrs(&self, dinode: &Dinode) -> Vec<u32> {
-        let mut addrs = Vec::new();
-        for v in dinode.addrs.iter() {
-            if let Some(addr) = v {
-                addrs.push(*addr);
+    // Whether `ancestor` is `node` or one of its ancestors, following the first parents
+    fn is_ancestor(ancestor: &Rc<Node<u16>>, node: &Rc<Node<u16>>) -> bool {
+        let mut node = Rc::clone(node);
+        loop {
+            if Rc::ptr_eq(ancestor, &node) {
+                return true;
+            }
+            let parent = match node.parents.borrow().first().and_then(|parent| parent.upgrade()) {
+                Some(parent) => parent,
+                None => return false,
+            };
+            // Only the root is its own parent
+            if Rc::ptr_eq(&parent, &node) {
+                return false;
             }
+            node = parent;
         }
+    }
 
-        if let Some(addr) = &dinode.addrs[NDIRECT] {
-            let datastart = self.superblock.size - self.superblock.nblocks;
-            let addrs_indirect = parser::parse_indirect_reference_block(&self.data[*addr as usize], datastart);
-            for v in addrs_indirect {
-                if let Some(addr) = v {
-                    addrs.push(addr)
+    // Blocks owned by the inode: direct blocks, the indirect block and the blocks listed in it
+    // Invalid addresses are rejected by parser::parse_fs in strict mode, and skipped here otherwise
+    pub fn get_inode_refs(&self, inum: u16) -> Vec<(BlockRef, BlockNo)> {
+        let dinode = &self.dinodes[inum as usize];
+        let mut refs = vec![];
+        for (slot, addr) in dinode.addrs[..NDIRECT].iter().enumerate() {
+            if let Some(addr) = addr {
+                refs.push((BlockRef::Direct { inum, slot }, *addr));
+            }
+        }
+        if let Some(addr) = dinode.addrs[NDIRECT] {
+            refs.push((BlockRef::IndirectBlock { inum }, addr));
+            if let Ok(addrs_indirect) = parser::parse_indirect_reference_block(self.block(addr), addr, &self.geometry, ParseMode::Lenient) {
+                for (slot, addr) in addrs_indirect.into_iter().enumerate() {
+                    if let Some(addr) = addr {
+                        refs.push((BlockRef::Indirect { inum, slot }, addr));
+                    }
                 }
             }
         }
+        refs
+    }
+
+    // References to each data block by allocated inodes, sorted
+    pub fn get_block_refs(&self) -> collections::HashMap<BlockNo, Vec<BlockRef>> {
+        self.collect_block_refs(false)
+    }
+
+    // References to each data block left in unused inodes, sorted
+    pub fn get_stale_block_refs(&self) -> collections::HashMap<BlockNo, Vec<BlockRef>> {
+        self.collect_block_refs(true)
+    }
+
+    fn collect_block_refs(&self, unused: bool) -> collections::HashMap<BlockNo, Vec<BlockRef>> {
+        let mut refs: collections::HashMap<BlockNo, Vec<BlockRef>> = collections::HashMap::new();
+        for (inum, dinode) in self.dinodes.iter().enumerate() {
+            if (dinode.typ == FileType::UNUSED) != unused {
+                continue;
+            }
+            for (r, addr) in self.get_inode_refs(inum as u16) {
+                refs.entry(addr).or_default().push(r);
+            }
+        }
+        for v in refs.values_mut() {
+            v.sort();
+        }
+        refs
+    }
+
+    // Data block of each block of the file up to its size, or None for a hole
+    // Blocks beyond MAXFILE are ignored
+    pub fn get_file_blocks(&self, dinode: &Dinode) -> Vec<Option<BlockNo>> {
+        let n = (dinode.size as usize).div_ceil(BSIZE).min(MAXFILE);
+        let mut blocks = dinode.addrs[..NDIRECT].to_vec();
+        if n > NDIRECT {
+            let indirect = dinode.addrs[NDIRECT]
+                .and_then(|addr| parser::parse_indirect_reference_block(self.block(addr), addr, &self.geometry, ParseMode::Lenient).ok());
+            blocks.extend(indirect.unwrap_or_else(|| vec![None; NINDIRECT]));
+        }
+        blocks.truncate(n);
+        blocks
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fs::error::FSError;
+    use crate::parser;
+    use crate::util::testing::{mkfs, set_entry, set_inode};
+
+    #[test]
+    fn directory_loop() {
+        let mut img = mkfs();
+        // /sub has "up" referring to the root
+        set_inode(&mut img, 2, 1, 1, BSIZE as u32, &[8]);
+        set_entry(&mut img, 7, 2, 2, "sub");
+        set_entry(&mut img, 8, 0, 2, ".");
+        set_entry(&mut img, 8, 1, 1, "..");
+        set_entry(&mut img, 8, 2, 1, "up");
+        img[6 * BSIZE + 1] = 0x01;
+        let fs = parser::parse_fs(&img, ParseMode::Strict).unwrap();
+        assert_eq!(fs.check_directory_ref(), vec![FSError::DirectoryLoop(2, 2, 1, "/sub/up".to_string())]);
+        assert_eq!(fs.get_path(&2).unwrap(), "/sub");
+    }
 
-        addrs
+    #[test]
+    fn directory_hard_link() {
+        let mut img = mkfs();
+        // /a/b is also linked as /c, which comes first in the root
+        set_inode(&mut img, 2, 1, 2, BSIZE as u32, &[8]);
+        set_inode(&mut img, 3, 1, 1, BSIZE as u32, &[9]);
+        set_entry(&mut img, 7, 2, 3, "c");
+        set_entry(&mut img, 7, 3, 2, "a");
+        set_entry(&mut img, 8, 0, 2, ".");
+        set_entry(&mut img, 8, 1, 1, "..");
+        set_entry(&mut img, 8, 2, 3, "b");
+        set_entry(&mut img, 9, 0, 3, ".");
+        set_entry(&mut img, 9, 1, 2, "..");
+        img[6 * BSIZE + 1] = 0x03;
+        let fs = parser::parse_fs(&img, ParseMode::Strict).unwrap();
+        // ".." of b decides the link kept in the tree
+        assert_eq!(fs.check_directory_ref(), vec![FSError::DirectoryHardLink(1, 2, 3, "/c".to_string())]);
+        assert_eq!(fs.get_path(&3).unwrap(), "/a/b");
+        assert!(fs.check_parent_directory().is_empty());
     }
 }
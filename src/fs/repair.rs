@@ -0,0 +1,221 @@
+mod repair_bitmap;
+mod repair_dot_entries;
+mod repair_duplicates;
+mod repair_multiple_ref;
+mod repair_nlink;
+mod repair_root;
+mod repair_unused;
+mod repair_lost_found;
+
+use super::*;
+use crate::fs::error::{FSError, Severity};
+use crate::writer;
+
+// What to do with files and devices that no directory refers to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Unlinked {
+    // Free the inode and its data blocks
+    Free,
+    // Link the inode into /lost+found
+    #[default]
+    Reconnect,
+}
+
+impl Dinode {
+    fn set_addr(&mut self, slot: usize, addr: Option<BlockNo>) {
+        self.addrs[slot] = addr;
+        self.raw_addrs[slot] = addr.map_or(0, |addr| addr.0);
+    }
+}
+
+impl FS {
+    // Findings which make repairs unsafe, so that nothing must be written while any is left
+    // Repairs write through the layout in the superblock, and the kernel would reinstall
+    // the logged blocks over the repaired ones on the next boot (see writer::replay_log)
+    pub fn repair_blockers(&self) -> Vec<FSError> {
+        let mut blockers: Vec<FSError> = self
+            .superblock
+            .check_fields()
+            .into_iter()
+            .filter(|e| e.severity() == Severity::Error)
+            .collect();
+        if self.log.as_ref().is_some_and(|log| log.n > 0) {
+            blockers.append(&mut self.check_log());
+        }
+        blockers
+    }
+
+    // Inode numbers referred by any directory entry, including unreachable directories
+    fn referenced_inodes(&self) -> collections::HashSet<u16> {
+        self.inum_to_dirents
+            .values()
+            .flatten()
+            .flatten()
+            .map(|dirent| dirent.inum)
+            .collect()
+    }
+
+    // A cleared data block which no inode refers to
+    // The caller must store it in an inode before allocating another block
+    fn alloc_block(&mut self) -> Option<BlockNo> {
+        let count = self.count_datablock_ref();
+        let metadata_end = self.geometry.metadata_end();
+        let addr = self.geometry.data_blocks().find(|addr| addr.0 >= metadata_end && !count.contains_key(addr))?;
+        self.block_mut(addr).fill(0);
+        self.bitmap[addr.0 as usize] = BlockStatus::Allocated;
+        Some(addr)
+    }
+
+    // An unused inode which no directory entry refers to, initialized with nlink 1
+    fn alloc_inode(&mut self, typ: FileType) -> Option<u16> {
+        let referenced = self.referenced_inodes();
+        let inum = (ROOTINO as usize..self.dinodes.len())
+            .find(|i| self.dinodes[*i].typ == FileType::UNUSED && !referenced.contains(&(*i as u16)))?;
+        self.dinodes[inum] = Dinode::new(typ, 0, 0, 1, 0, [None; NDIRECT + 1], [0; NDIRECT + 1]);
+        Some(inum as u16)
+    }
+
+    // Undo alloc_block for a block which is no longer referred to
+    fn free_block(&mut self, addr: BlockNo) {
+        self.bitmap[addr.0 as usize] = BlockStatus::Free;
+    }
+
+    // Block of the inode at block `fbn` of the file, allocated if missing like bmap in xv6
+    // Nothing stays allocated when this fails
+    fn bmap(&mut self, inum: u16, fbn: usize) -> Option<BlockNo> {
+        if fbn < NDIRECT {
+            if let Some(addr) = self.dinodes[inum as usize].addrs[fbn] {
+                return Some(addr);
+            }
+            let addr = self.alloc_block()?;
+            self.dinodes[inum as usize].set_addr(fbn, Some(addr));
+            return Some(addr);
+        }
+
+        let slot = fbn - NDIRECT;
+        if slot >= NINDIRECT {
+            return None;
+        }
+        let (indirect, allocated) = match self.dinodes[inum as usize].addrs[NDIRECT] {
+            Some(addr) => (addr, false),
+            None => {
+                let addr = self.alloc_block()?;
+                self.dinodes[inum as usize].set_addr(NDIRECT, Some(addr));
+                (addr, true)
+            }
+        };
+        let entry = &self.block(indirect)[slot * 4..][..4];
+        let addr = u32::from_le_bytes(entry.try_into().unwrap());
+        if self.geometry.in_data_region(addr) {
+            return Some(BlockNo(addr));
+        }
+        match self.alloc_block() {
+            Some(addr) => {
+                self.block_mut(indirect)[slot * 4..][..4].copy_from_slice(&addr.0.to_le_bytes());
+                Some(addr)
+            }
+            None => {
+                if allocated {
+                    self.dinodes[inum as usize].set_addr(NDIRECT, None);
+                    self.free_block(indirect);
+                }
+                None
+            }
+        }
+    }
+
+    fn free_inode(&mut self, inum: u16) {
+        self.dinodes[inum as usize] = Dinode::new(FileType::UNUSED, 0, 0, 0, 0, [None; NDIRECT + 1], [0; NDIRECT + 1]);
+    }
+
+    // Offsets of the entries in the blocks of the directory, skipping holes
+    fn dirent_offsets(&self, dir: u16) -> Vec<usize> {
+        let dinode = &self.dinodes[dir as usize];
+        let blocks = self.get_file_blocks(dinode);
+        let end = (dinode.size as usize).min(blocks.len() * BSIZE);
+        (0..end)
+            .step_by(DIRENTSIZE)
+            .filter(|off| off + DIRENTSIZE <= end && blocks[off / BSIZE].is_some())
+            .collect()
+    }
+
+    // Raw entry at `offset` of the directory, which must be one of dirent_offsets
+    fn dirent_at(&mut self, dir: u16, offset: usize) -> &mut [u8] {
+        let addr = self.get_file_blocks(&self.dinodes[dir as usize])[offset / BSIZE].unwrap();
+        &mut self.block_mut(addr)[offset % BSIZE..][..DIRENTSIZE]
+    }
+
+    // Point the first entry named `name` to `inum`, or add the entry if there is none
+    fn set_dirent(&mut self, dir: u16, name: &str, inum: u16) -> bool {
+        let encoded = writer::encode_dirent(0, name);
+        for offset in self.dirent_offsets(dir) {
+            let dirent = self.dirent_at(dir, offset);
+            if dirent[0..2] != [0, 0] && dirent[2..] == encoded[2..] {
+                dirent[0..2].copy_from_slice(&inum.to_le_bytes());
+                return true;
+            }
+        }
+        self.add_dirent(dir, inum, name)
+    }
+
+    // Append an entry to the directory like dirlink in xv6, reusing a free slot if any
+    // Fails when the directory reaches MAXFILE blocks or no block is free
+    fn add_dirent(&mut self, dir: u16, inum: u16, name: &str) -> bool {
+        self.add_raw_dirent(dir, writer::encode_dirent(inum, name))
+    }
+
+    // add_dirent with an encoded entry, such as one moved from another slot
+    fn add_raw_dirent(&mut self, dir: u16, dirent: [u8; DIRENTSIZE]) -> bool {
+        let size = self.dinodes[dir as usize].size as usize;
+
+        let offset = self
+            .dirent_offsets(dir)
+            .into_iter()
+            .find(|offset| self.dirent_at(dir, *offset)[0..2] == [0, 0])
+            // A size broken in the middle of an entry is rounded up
+            .unwrap_or(size.next_multiple_of(DIRENTSIZE));
+
+        let addr = match self.bmap(dir, offset / BSIZE) {
+            Some(addr) => addr,
+            None => return false,
+        };
+        self.block_mut(addr)[offset % BSIZE..][..DIRENTSIZE].copy_from_slice(&dirent);
+        if offset >= size {
+            self.dinodes[dir as usize].size = (offset + DIRENTSIZE) as u32;
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser;
+    use crate::parser::ParseMode;
+    use crate::util::testing::mkfs;
+
+    #[test]
+    fn repair_blockers() {
+        let mut img = mkfs();
+        // block 7 is committed in the log, and the bitmap misses it
+        img.copy_within(7 * BSIZE..8 * BSIZE, 3 * BSIZE);
+        img[2 * BSIZE..][..8].copy_from_slice(&[1, 0, 0, 0, 7, 0, 0, 0]);
+        img[6 * BSIZE] = 0x7f;
+        let fs = parser::parse_fs(&img, ParseMode::Strict).unwrap();
+        assert_eq!(fs.check_bitmap().len(), 1);
+        assert_eq!(fs.repair_blockers().len(), 1);
+        assert_eq!(writer::replay_log(&fs, &mut img), 1);
+        let fs = parser::parse_fs(&img, ParseMode::Strict).unwrap();
+        assert!(fs.repair_blockers().is_empty());
+    }
+
+    #[test]
+    fn repair_blockers_broken_log() {
+        let mut img = mkfs();
+        // the log header lists block 0, which is not loggable
+        img[2 * BSIZE..][..8].copy_from_slice(&[1, 0, 0, 0, 0, 0, 0, 0]);
+        let fs = parser::parse_fs(&img, ParseMode::Strict).unwrap();
+        assert!(!fs.repair_blockers().is_empty());
+        assert_eq!(writer::replay_log(&fs, &mut img), 0);
+    }
+}
@@ -0,0 +1,26 @@
+use super::*;
+
+impl FS {
+    // Rebuild the bitmap from the references to data blocks by allocated inodes
+    // Blocks before the data region are always allocated, and bits beyond the image are always free
+    // Returns the number of corrected bits
+    pub fn repair_bitmap(&mut self) -> usize {
+        let count = self.count_datablock_ref();
+        let datastart = self.geometry.datastart as usize;
+        let size = self.geometry.size as usize;
+        let mut fixed = 0;
+        for (i, bmap) in self.bitmap.iter_mut().enumerate() {
+            let used = match i {
+                _ if i < datastart => true,
+                _ if i < size => count.get(&BlockNo(i as u32)).is_some_and(|count| count.live > 0),
+                _ => false,
+            };
+            let status = if used { BlockStatus::Allocated } else { BlockStatus::Free };
+            if *bmap != status {
+                *bmap = status;
+                fixed += 1;
+            }
+        }
+        fixed
+    }
+}
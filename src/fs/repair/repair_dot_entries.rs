@@ -0,0 +1,140 @@
+use super::*;
+use crate::fs::error::FSError;
+
+impl FS {
+    // Directory which ".." of `dir` should refer to
+    // An unreachable directory keeps its current parent, which repair_orphans replaces later
+    fn expected_parent(&self, dir: u16) -> u16 {
+        if let Some(node) = self.get_node(&dir) {
+            if let Some(parent) = node.parents.borrow().first().and_then(|parent| parent.upgrade()) {
+                return parent.value;
+            }
+        }
+        self.get_dirents(&dir)
+            .into_iter()
+            .flatten()
+            .find(|dirent| dirent.name == "..")
+            .map_or(ROOTINO, |dirent| dirent.inum)
+    }
+
+    // Write "." and ".." into the first two slots of the directory
+    // Other entries in these slots are moved to free slots, and other "." and ".." entries are cleared
+    fn repair_dot_entries_individual(&mut self, dir: u16) -> bool {
+        let parent = self.expected_parent(dir);
+        let size = self.dinodes[dir as usize].size as usize;
+        let canonical = [(CURRENT_DIR_OFFSET, dir, "."), (PARENT_DIR_OFFSET, parent, "..")];
+
+        let mut displaced = vec![];
+        let mut misplaced = vec![];
+        for dirent in self.get_dirents(&dir).into_iter().flatten() {
+            let is_dot = dirent.name_bytes() == b"." || dirent.name_bytes() == b"..";
+            if dirent.offset <= PARENT_DIR_OFFSET {
+                if !is_dot {
+                    let mut raw = [0; DIRENTSIZE];
+                    raw[0..2].copy_from_slice(&dirent.inum.to_le_bytes());
+                    raw[2..].copy_from_slice(&dirent.raw_name);
+                    displaced.push(raw);
+                }
+            } else if is_dot {
+                misplaced.push(dirent.offset);
+            }
+        }
+
+        if self.dinodes[dir as usize].addrs[0].is_none() {
+            match self.alloc_block() {
+                Some(addr) => self.dinodes[dir as usize].set_addr(0, Some(addr)),
+                None => return false,
+            }
+        }
+        for offset in misplaced {
+            self.dirent_at(dir, offset)[0..2].copy_from_slice(&[0, 0]);
+        }
+        self.dinodes[dir as usize].size = size.max(PARENT_DIR_OFFSET + DIRENTSIZE) as u32;
+        for (offset, inum, name) in canonical {
+            self.dirent_at(dir, offset).copy_from_slice(&writer::encode_dirent(inum, name));
+        }
+        // An entry which does not fit is dropped, and its inode is left to the other repairs
+        displaced.into_iter().all(|dirent| self.add_raw_dirent(dir, dirent))
+    }
+
+    // Rewrite "." and ".." of every directory in which they are missing, misplaced or wrong,
+    // except for directories linked by more than one entry
+    // Returns the number of repaired directories
+    pub fn repair_dot_entries(&mut self) -> usize {
+        let mut broken: Vec<u16> = self
+            .check_current_directory()
+            .into_iter()
+            .chain(self.check_parent_directory())
+            .chain(self.check_root())
+            .filter_map(|e| match e {
+                FSError::MissingCurrentDirEntry(inum)
+                | FSError::MisplacedCurrentDirEntry(inum, _)
+                | FSError::IncorrectCurrentDirRef(inum, _)
+                | FSError::MissingParentDirEntry(inum)
+                | FSError::MisplacedParentDirEntry(inum, _)
+                | FSError::IncorrectParentDirRef(inum, _, _) => Some(inum),
+                FSError::IncorrectRootDirRef(..) => Some(ROOTINO),
+                _ => None,
+            })
+            .collect();
+        broken.sort();
+        broken.dedup();
+        // The parent of a directory linked by several entries is ambiguous, so its ".." is left as it is
+        let ambiguous: collections::HashSet<u16> = self
+            .tree_errors
+            .iter()
+            .filter_map(|e| match e {
+                FSError::DirectoryHardLink(_, _, inum, _) | FSError::DirectoryLoop(_, _, inum, _) => Some(*inum),
+                _ => None,
+            })
+            .collect();
+        broken.retain(|inum| !ambiguous.contains(inum));
+
+        let mut fixed = 0;
+        for dir in broken {
+            if self.repair_dot_entries_individual(dir) {
+                fixed += 1;
+            }
+        }
+        self.reload();
+        fixed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser;
+    use crate::parser::ParseMode;
+    use crate::util::testing::{mkfs, set_entry, set_inode};
+    use crate::writer::write_fs;
+
+    #[test]
+    fn repair_dot_entries() {
+        let mut img = mkfs();
+        // /sub has an extra ".." at slot 3 and an extra "." at slot 4 after its file f
+        set_entry(&mut img, 7, 2, 2, "sub");
+        set_inode(&mut img, 1, 1, 2, 3 * DIRENTSIZE as u32, &[7]);
+        set_inode(&mut img, 2, 1, 1, 5 * DIRENTSIZE as u32, &[8]);
+        set_entry(&mut img, 8, 0, 2, ".");
+        set_entry(&mut img, 8, 1, 1, "..");
+        set_entry(&mut img, 8, 2, 3, "f");
+        set_entry(&mut img, 8, 3, 2, "..");
+        set_entry(&mut img, 8, 4, 2, ".");
+        set_inode(&mut img, 3, 2, 1, 0, &[]);
+        img[6 * BSIZE + 1] = 0x01;
+
+        let mut fs = parser::parse_fs(&img, ParseMode::Strict).unwrap();
+        assert_eq!(fs.check_current_directory(), vec![FSError::MisplacedCurrentDirEntry(2, 4 * DIRENTSIZE)]);
+        assert_eq!(fs.check_parent_directory(), vec![FSError::MisplacedParentDirEntry(2, 3 * DIRENTSIZE)]);
+        assert_eq!(fs.repair_duplicate_names(), 0);
+        assert_eq!(fs.repair_dot_entries(), 1);
+        write_fs(&fs, &mut img);
+
+        let fs = parser::parse_fs(&img, ParseMode::Strict).unwrap();
+        assert_eq!(fs.find_dirent(2, "f").map(|dirent| dirent.inum), Some(3));
+        assert!(fs.check_current_directory().is_empty());
+        assert!(fs.check_parent_directory().is_empty());
+        assert!(fs.check_duplicate_names().is_empty());
+    }
+}
@@ -0,0 +1,49 @@
+use super::*;
+
+impl FS {
+    // A name for a later duplicate, made unique among `taken` by replacing its tail with "~<n>"
+    fn unique_name(name: &[u8], taken: &collections::HashSet<Vec<u8>>) -> Vec<u8> {
+        (1..)
+            .map(|n| {
+                let suffix = format!("~{}", n).into_bytes();
+                let mut candidate = name[..name.len().min(DIRSIZ - suffix.len())].to_vec();
+                candidate.extend(suffix);
+                candidate
+            })
+            .find(|candidate| !taken.contains(candidate))
+            .unwrap()
+    }
+
+    // Rename the later entries which have the same name as an earlier one in the directory
+    // Extra "." and ".." entries are left to repair_dot_entries, which clears them
+    // Returns the number of renamed entries
+    pub fn repair_duplicate_names(&mut self) -> usize {
+        let mut fixed = 0;
+        for dir in 0..self.dinodes.len() as u16 {
+            let duplicates: Vec<(usize, Vec<u8>)> = self
+                .duplicate_dirents(dir)
+                .into_iter()
+                .filter(|(_, later)| !matches!(later.name_bytes(), b"." | b".."))
+                .map(|(_, later)| (later.offset, later.name_bytes().to_vec()))
+                .collect();
+            if duplicates.is_empty() {
+                continue;
+            }
+
+            let mut taken: collections::HashSet<Vec<u8>> =
+                self.get_dirents(&dir).into_iter().flatten().map(|dirent| dirent.name_bytes().to_vec()).collect();
+            for (offset, name) in duplicates {
+                let renamed = Self::unique_name(&name, &taken);
+                let mut raw_name = [0; DIRSIZ];
+                raw_name[..renamed.len()].copy_from_slice(&renamed);
+                self.dirent_at(dir, offset)[2..].copy_from_slice(&raw_name);
+                taken.insert(renamed);
+                fixed += 1;
+            }
+        }
+        if fixed > 0 {
+            self.reload();
+        }
+        fixed
+    }
+}
@@ -0,0 +1,197 @@
+use super::*;
+
+impl FS {
+    // Inode number of /lost+found, which is created if it does not exist
+    fn lost_found(&mut self) -> Option<u16> {
+        if self.dinodes.get(ROOTINO as usize)?.typ != FileType::DIR {
+            return None;
+        }
+        if let Some(dirent) = self.get_dirents(&ROOTINO)?.iter().find(|dirent| dirent.name == "lost+found") {
+            // Do not replace a file which has the same name
+            let dinode = self.dinodes.get(dirent.inum as usize)?;
+            return if dinode.typ == FileType::DIR { Some(dirent.inum) } else { None };
+        }
+
+        let inum = self.alloc_inode(FileType::DIR)?;
+        let linked = self.add_dirent(inum, inum, ".")
+            && self.add_dirent(inum, ROOTINO, "..")
+            && self.add_dirent(ROOTINO, inum, "lost+found");
+        if !linked {
+            // Release the blocks already allocated for the new directory
+            for (_, addr) in self.get_inode_refs(inum) {
+                self.free_block(addr);
+            }
+            self.free_inode(inum);
+            return None;
+        }
+        // reference by ".." of lost+found
+        self.dinodes[ROOTINO as usize].nlink += 1;
+        self.reload();
+        Some(inum)
+    }
+
+    // Files and devices which no directory entry refers to, that is, whose link count must be 0
+    fn unlinked_files(&self) -> Vec<u16> {
+        let referenced = self.referenced_inodes();
+        self.dinodes
+            .iter()
+            .enumerate()
+            .filter(|(inum, dinode)| {
+                matches!(dinode.typ, FileType::FILE | FileType::DEV) && !referenced.contains(&(*inum as u16))
+            })
+            .map(|(inum, _)| inum as u16)
+            .collect()
+    }
+
+    // Free unlinked files, or link them into /lost+found by the name "#<inode number>"
+    // Returns the number of handled inodes
+    pub fn repair_unlinked(&mut self, policy: Unlinked) -> usize {
+        let unlinked = self.unlinked_files();
+        if unlinked.is_empty() {
+            return 0;
+        }
+
+        let mut fixed = 0;
+        match policy {
+            Unlinked::Free => {
+                for inum in unlinked {
+                    self.free_inode(inum);
+                    fixed += 1;
+                }
+            }
+            Unlinked::Reconnect => {
+                let lost_found = match self.lost_found() {
+                    Some(inum) => inum,
+                    None => return 0,
+                };
+                for inum in unlinked {
+                    if self.reconnect(lost_found, inum) {
+                        self.dinodes[inum as usize].nlink = 1;
+                        fixed += 1;
+                    }
+                }
+            }
+        }
+        self.reload();
+        fixed
+    }
+
+    // Allocated inodes which cannot be traced from the root directory
+    fn orphans(&self) -> Vec<u16> {
+        self.dinodes
+            .iter()
+            .enumerate()
+            .filter(|(inum, dinode)| {
+                matches!(dinode.typ, FileType::FILE | FileType::DIR | FileType::DEV) && self.get_node(&(*inum as u16)).is_none()
+            })
+            .map(|(inum, _)| inum as u16)
+            .collect()
+    }
+
+    // Link an orphan into /lost+found by the name "#<inode number>"
+    fn reconnect(&mut self, lost_found: u16, inum: u16) -> bool {
+        if !self.add_dirent(lost_found, inum, &format!("#{}", inum)) {
+            return false;
+        }
+        if self.dinodes[inum as usize].typ == FileType::DIR {
+            // reference by ".." of the orphan
+            self.dinodes[lost_found as usize].nlink += 1;
+            self.set_dirent(inum, "..", lost_found)
+        } else {
+            true
+        }
+    }
+
+    // Link every orphan into /lost+found like fsck
+    // Orphans in orphaned directories become reachable with their directory, so only the topmost ones are linked
+    // Returns the number of linked inodes
+    pub fn repair_orphans(&mut self) -> usize {
+        let mut fixed = 0;
+        loop {
+            let orphans = self.orphans();
+            if orphans.is_empty() {
+                break;
+            }
+            // Orphans referred by other orphaned directories
+            let mut hidden = collections::HashSet::new();
+            for inum in orphans.iter() {
+                if let Some(dirents) = self.get_dirents(inum) {
+                    for dirent in dirents.iter().filter(|dirent| dirent.name != "." && dirent.name != "..") {
+                        if dirent.inum != *inum {
+                            hidden.insert(dirent.inum);
+                        }
+                    }
+                }
+            }
+            let mut tops: Vec<u16> = orphans.iter().filter(|inum| !hidden.contains(inum)).copied().collect();
+            if tops.is_empty() {
+                // Orphaned directories refer to each other, so break the cycle at one of them
+                tops.push(orphans[0]);
+            }
+
+            let lost_found = match self.lost_found() {
+                Some(inum) => inum,
+                None => break,
+            };
+            let mut progress = false;
+            for inum in tops {
+                if self.reconnect(lost_found, inum) {
+                    fixed += 1;
+                    progress = true;
+                }
+            }
+            self.reload();
+            if !progress {
+                break;
+            }
+        }
+        fixed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fs::error::FSError;
+    use crate::parser;
+    use crate::parser::ParseMode;
+    use crate::util::testing::{mkfs, set_entry, set_inode};
+    use crate::writer::write_fs;
+
+    #[test]
+    fn repair_lost_found() {
+        let mut img = mkfs();
+        // The root is full up to its size 1020, which is not a multiple of DIRENTSIZE
+        let nlink = (2..BSIZE / DIRENTSIZE - 1).len() as u16;
+        for index in 2..BSIZE / DIRENTSIZE - 1 {
+            set_entry(&mut img, 7, index, 3, &format!("f{}", index));
+        }
+        set_inode(&mut img, 1, 1, 1, BSIZE as u32 - 4, &[7]);
+        set_inode(&mut img, 3, 2, nlink, 0, &[]);
+        // File 2 is unlinked, and directory 4 is orphaned
+        set_inode(&mut img, 2, 2, 1, 0, &[]);
+        set_inode(&mut img, 4, 1, 1, 2 * DIRENTSIZE as u32, &[8]);
+        set_entry(&mut img, 8, 0, 4, ".");
+        set_entry(&mut img, 8, 1, 1, "..");
+        img[6 * BSIZE + 1] = 0x01;
+
+        let mut fs = parser::parse_fs(&img, ParseMode::Strict).unwrap();
+        assert_eq!(fs.repair_unlinked(Unlinked::Reconnect), 1);
+        assert_eq!(fs.repair_orphans(), 1);
+        fs.repair_nlink();
+        fs.repair_bitmap();
+        write_fs(&fs, &mut img);
+
+        let fs = parser::parse_fs(&img, ParseMode::Strict).unwrap();
+        let lost_found = fs.get_dirents(&ROOTINO).unwrap().iter().find(|dirent| dirent.name == "lost+found").unwrap().inum;
+        assert_eq!(fs.get_path(&2).unwrap(), "/lost+found/#2");
+        assert_eq!(fs.get_path(&4).unwrap(), "/lost+found/#4");
+        assert_eq!(fs.find_dirent(4, "..").unwrap().inum, lost_found);
+        assert_eq!(fs.dinodes[ROOTINO as usize].size as usize, BSIZE + DIRENTSIZE);
+        assert!(fs.check_directory_ref().is_empty());
+        assert!(fs.check_parent_directory().is_empty());
+        assert!(fs.check_nlink().is_empty());
+        assert!(fs.check_bitmap().is_empty());
+        assert!(fs.check_addrs_len().iter().all(|e| matches!(e, FSError::MissingBlocks(..))));
+    }
+}
@@ -0,0 +1,75 @@
+use super::*;
+
+impl FS {
+    // Give each extra claimant of a data block its own copy of the block, like pass 1B-1D of e2fsck
+    // The first reference in BlockRef order keeps the original block
+    // Returns the number of cloned blocks
+    pub fn repair_multiple_ref(&mut self) -> usize {
+        let mut cloned = 0;
+        // Shared indirect blocks are cloned first, so that the entries are rewritten in the inode's own copy
+        for indirect_only in [true, false] {
+            let mut refs: Vec<_> = self.get_block_refs().into_iter().filter(|(_, refs)| refs.len() > 1).collect();
+            refs.sort();
+            for (addr, refs) in refs {
+                for r in refs.into_iter().skip(1) {
+                    if indirect_only && !matches!(r, BlockRef::IndirectBlock { .. }) {
+                        continue;
+                    }
+                    if !self.clone_block(addr, r) {
+                        return cloned;
+                    }
+                    cloned += 1;
+                }
+            }
+        }
+        cloned
+    }
+
+    // Copy the data block to a new block and point the reference to it
+    fn clone_block(&mut self, addr: BlockNo, r: BlockRef) -> bool {
+        let new = match self.alloc_block() {
+            Some(new) => new,
+            None => return false,
+        };
+        let content = self.block(addr).to_vec();
+        self.block_mut(new).copy_from_slice(&content);
+        match r {
+            BlockRef::IndirectBlock { inum } => self.dinodes[inum as usize].set_addr(NDIRECT, Some(new)),
+            BlockRef::Direct { inum, slot } => self.dinodes[inum as usize].set_addr(slot, Some(new)),
+            BlockRef::Indirect { inum, slot } => {
+                let indirect = self.dinodes[inum as usize].addrs[NDIRECT].unwrap();
+                self.block_mut(indirect)[slot * 4..][..4].copy_from_slice(&new.0.to_le_bytes());
+            }
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser;
+    use crate::parser::ParseMode;
+    use crate::util::testing::{mkfs, set_entry, set_inode};
+    use crate::writer::write_fs;
+
+    #[test]
+    fn repair_multiple_ref() {
+        let mut img = mkfs();
+        // Files x and y share direct block 8 and indirect block 9, which lists block 10
+        for (inum, name) in [(2, "x"), (3, "y")] {
+            set_inode(&mut img, inum, 2, 1, 13 * BSIZE as u32, &[8, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 9]);
+            set_entry(&mut img, 7, inum as usize, inum, name);
+        }
+        img[9 * BSIZE..][..4].copy_from_slice(&10u32.to_le_bytes());
+        img[6 * BSIZE + 1] = 0x07;
+        let mut fs = parser::parse_fs(&img, ParseMode::Strict).unwrap();
+        assert_eq!(fs.check_datablock_ref().len(), 3);
+        assert_eq!(fs.repair_multiple_ref(), 3);
+        fs.repair_bitmap();
+        write_fs(&fs, &mut img);
+        let fs = parser::parse_fs(&img, ParseMode::Strict).unwrap();
+        assert!(fs.check_datablock_ref().is_empty());
+        assert!(fs.check_bitmap().is_empty());
+    }
+}
@@ -0,0 +1,17 @@
+use super::*;
+use crate::fs::error::FSError;
+
+impl FS {
+    // Set nlink to the number of references in the directory tree
+    // Returns the number of corrected inodes
+    pub fn repair_nlink(&mut self) -> usize {
+        let mut fixed = 0;
+        for e in self.check_nlink() {
+            if let FSError::IncorrectNLink(inum, _, count) = e {
+                self.dinodes[inum as usize].nlink = count;
+                fixed += 1;
+            }
+        }
+        fixed
+    }
+}
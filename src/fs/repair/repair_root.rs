@@ -0,0 +1,22 @@
+use super::*;
+use crate::fs::error::FSError;
+
+impl FS {
+    // Recreate an empty root directory if the root inode is unused or not a directory
+    // Its old content is discarded, and the inodes below are reconnected by repair_unlinked and repair_orphans
+    // Returns 1 if the root is recreated
+    pub fn repair_root(&mut self) -> usize {
+        let unusable = self
+            .check_root()
+            .iter()
+            .any(|e| matches!(e, FSError::UnusedRootInode | FSError::RootNotDirectory(_)));
+        if !unusable {
+            return 0;
+        }
+
+        self.dinodes[ROOTINO as usize] = Dinode::new(FileType::DIR, 0, 0, 1, 0, [None; NDIRECT + 1], [0; NDIRECT + 1]);
+        let created = self.add_dirent(ROOTINO, ROOTINO, ".") && self.add_dirent(ROOTINO, ROOTINO, "..");
+        self.reload();
+        if created { 1 } else { 0 }
+    }
+}
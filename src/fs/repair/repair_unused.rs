@@ -0,0 +1,17 @@
+use super::*;
+
+impl FS {
+    // Zero unused inodes left by an incomplete itrunc, dropping their stale block references
+    // Returns the number of cleared inodes
+    pub fn repair_unused_inodes(&mut self) -> usize {
+        let mut fixed = 0;
+        for inum in 0..self.dinodes.len() {
+            let dinode = &self.dinodes[inum];
+            if dinode.typ == FileType::UNUSED && !Self::dirty_fields(dinode).is_empty() {
+                self.free_inode(inum as u16);
+                fixed += 1;
+            }
+        }
+        fixed
+    }
+}
@@ -1,3 +1,4 @@
 pub mod fs;
 pub mod parser;
 pub mod util;
+pub mod writer;
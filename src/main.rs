@@ -1,93 +1,264 @@
-use console::{style, Emoji};
-use xv6_fsck::fs::error::FSError;
+mod output;
+
+use std::process::ExitCode;
+use console::Emoji;
+use xv6_fsck::fs::check::{Profile, XV6_PROFILE};
+use xv6_fsck::fs::error::{FSError, Severity};
+use xv6_fsck::fs::repair::Unlinked;
+use xv6_fsck::fs::FS;
 use xv6_fsck::parser;
+use xv6_fsck::parser::ParseMode;
+use xv6_fsck::writer;
+use crate::output::{Format, Output};
 
 static LOOKING_GLASS: Emoji<'_, '_> = Emoji("🔍", "");
 static SUPER: Emoji<'_, '_> = Emoji("👑", "");
+static LOG: Emoji<'_, '_> = Emoji("📜", "");
 static BLOCK: Emoji<'_, '_> = Emoji("🧱", "");
+static ROOT: Emoji<'_, '_> = Emoji("🌳", "");
 static DIR: Emoji<'_, '_> = Emoji("📁", "");
 static FILE: Emoji<'_, '_> = Emoji("📄", "");
-static SPARKLE: Emoji<'_, '_> = Emoji("✨", ":-)");
-static ERROR: Emoji<'_, '_> = Emoji("❌", ":-(");
+static WRENCH: Emoji<'_, '_> = Emoji("🔧", "");
+
+// Exit code of e2fsck for usage or syntax errors
+const EXIT_USAGE: u8 = 16;
+
+const USAGE: &str = "[--lenient] [--profile xv6] [--format human|json|jsonl] [--replay-log] [--repair [--unlinked free|reconnect] [--rename-duplicates]] [--output <path>] <path>";
+
+struct Options {
+    path: String,
+    mode: ParseMode,
+    profile: Option<Profile>,
+    format: Format,
+    repair: bool,
+    // Install committed blocks of the log before checking
+    replay_log: bool,
+    // Where the repaired image is written, instead of overwriting `path`
+    output: Option<String>,
+    unlinked: Unlinked,
+    // Rename entries whose name is used by an earlier entry in the same directory
+    rename_duplicates: bool,
+}
 
-fn handle_errors(errors: &[FSError]) {
-    for e in errors {
-        eprintln!("{}: {}", style("error").bold().red(), style(e).bold());
+fn parse_args(args: &[String]) -> Option<Options> {
+    let mut mode = ParseMode::Strict;
+    let mut profile = None;
+    let mut format = Format::Human;
+    let mut repair = false;
+    let mut replay_log = false;
+    let mut output = None;
+    let mut unlinked = None;
+    let mut rename_duplicates = false;
+    let mut paths = vec![];
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--lenient" => mode = ParseMode::Lenient,
+            "--profile" => match iter.next()?.as_str() {
+                "xv6" => profile = Some(XV6_PROFILE),
+                _ => return None,
+            },
+            "--format" => format = Format::parse(iter.next()?)?,
+            "--repair" => repair = true,
+            "--replay-log" => replay_log = true,
+            "--output" => output = Some(iter.next()?.clone()),
+            "--unlinked" => match iter.next()?.as_str() {
+                "free" => unlinked = Some(Unlinked::Free),
+                "reconnect" => unlinked = Some(Unlinked::Reconnect),
+                _ => return None,
+            },
+            "--rename-duplicates" => rename_duplicates = true,
+            _ if arg.starts_with("--") => return None,
+            _ => paths.push(arg.clone()),
+        }
     }
+    if paths.len() != 1 || (output.is_some() && !repair && !replay_log) || ((unlinked.is_some() || rename_duplicates) && !repair) {
+        return None;
+    }
+    Some(Options { path: paths.pop()?, mode, profile, format, repair, replay_log, output, unlinked: unlinked.unwrap_or_default(), rename_duplicates })
 }
 
-fn main() {
-    let args: Vec<String> = std::env::args().collect();
-    if args.len() != 2 {
-        eprintln!("Usage: {} <path>", args[0]);
-        std::process::exit(1);
+struct Phase {
+    step: &'static str,
+    emoji: &'static Emoji<'static, 'static>,
+    title: &'static str,
+    id: &'static str,
+    check: fn(&FS, &Options) -> Vec<FSError>,
+}
+
+fn check_superblock(fs: &FS, opts: &Options) -> Vec<FSError> {
+    let mut errors = fs.superblock.check_fields();
+    if let Some(profile) = &opts.profile {
+        errors.append(&mut fs.superblock.check_profile(profile));
     }
-    let path = &args[1];
+    errors
+}
 
-    let mut errors = vec![];
-    let mut has_error = false;
+fn check_log(fs: &FS, _: &Options) -> Vec<FSError> {
+    fs.check_log()
+}
 
-    /* Parse */
-    println!(
-        "{} {} Parsing xv6 filesystem image...",
-        style("[1/5]").bold().dim(),
-        LOOKING_GLASS
-    );
-    let img = parser::read_img(path);
-    let fs = parser::parse_fs(&img);
-
-    /* Check superblock */
-    println!(
-        "{} {} Checking superblock...",
-        style("[2/5]").bold().dim(),
-        SUPER
-    );
-    errors.append(&mut fs.superblock.check_fields());
-    has_error |= !errors.is_empty();
-    handle_errors(&errors);
-    errors.clear();
-
-    /* Check block usage */
-    println!(
-        "{} {} Checking block usage...",
-        style("[3/5]").bold().dim(),
-        BLOCK
-    );
-    errors.append(&mut fs.check_datablock_ref());
+fn check_block_usage(fs: &FS, _: &Options) -> Vec<FSError> {
+    let mut errors = fs.check_datablock_ref();
     errors.append(&mut fs.check_bitmap());
-    has_error |= !errors.is_empty();
-    handle_errors(&errors);
-    errors.clear();
-
-    /* Check directory */
-    println!(
-        "{} {} Checking directory...",
-        style("[4/5]").bold().dim(),
-        DIR
-    );
-    errors.append(&mut fs.check_current_directory());
+    errors
+}
+
+fn check_root(fs: &FS, _: &Options) -> Vec<FSError> {
+    fs.check_root()
+}
+
+fn check_directory(fs: &FS, _: &Options) -> Vec<FSError> {
+    let mut errors = fs.check_current_directory();
     errors.append(&mut fs.check_parent_directory());
-    has_error |= !errors.is_empty();
-    handle_errors(&errors);
-    errors.clear();
-
-    /* Check inode */
-    println!(
-        "{} {} Checking inode...",
-        style("[5/5]").bold().dim(),
-        FILE
-    );
+    errors.append(&mut fs.check_directory_ref());
+    errors.append(&mut fs.check_dirents());
+    errors.append(&mut fs.check_dirent_names());
+    errors.append(&mut fs.check_duplicate_names());
+    errors
+}
+
+fn check_inode(fs: &FS, _: &Options) -> Vec<FSError> {
+    let mut errors = fs.check_file_types();
+    errors.append(&mut fs.check_addrs_range());
     errors.append(&mut fs.check_device_numbers());
     errors.append(&mut fs.check_nlink());
     errors.append(&mut fs.check_addrs_ref());
-    // errors.append(&mut fs.check_addrs_len());
-    has_error |= !errors.is_empty();
-    handle_errors(&errors);
-    errors.clear();
-
-    if has_error {
-        println!("{} {}", ERROR, style("Found errors").bold());
-    } else {
-        println!("{} {}", SPARKLE, style("No errors").bold());
+    errors.append(&mut fs.check_addrs_len());
+    errors.append(&mut fs.check_unused_inodes());
+    errors
+}
+
+static PHASES: [Phase; 6] = [
+    Phase { step: "[2/7]", emoji: &SUPER, title: "Checking superblock...", id: "superblock", check: check_superblock },
+    Phase { step: "[3/7]", emoji: &LOG, title: "Checking log...", id: "log", check: check_log },
+    Phase { step: "[4/7]", emoji: &BLOCK, title: "Checking block usage...", id: "block_usage", check: check_block_usage },
+    Phase { step: "[5/7]", emoji: &ROOT, title: "Checking root inode...", id: "root", check: check_root },
+    Phase { step: "[6/7]", emoji: &DIR, title: "Checking directory...", id: "directory", check: check_directory },
+    Phase { step: "[7/7]", emoji: &FILE, title: "Checking inode...", id: "inode", check: check_inode },
+];
+
+// Write the image to --output, or back to the input path
+fn save(img: &[u8], opts: &Options) -> Result<(), String> {
+    let path = opts.output.as_ref().unwrap_or(&opts.path);
+    std::fs::write(path, img).map_err(|e| format!("failed to write {}: {}", path, e))
+}
+
+// Returns the number of corrections for each kind of repair
+fn repair(fs: &mut FS, opts: &Options) -> Vec<(&'static str, usize)> {
+    let mut repairs = vec![];
+    // Everything else is reattached below the recreated root
+    repairs.push(("root directory recreated", fs.repair_root()));
+    // Stale references must be gone before blocks are cloned or allocated
+    repairs.push(("half-freed inodes cleared", fs.repair_unused_inodes()));
+    repairs.push(("shared data blocks cloned", fs.repair_multiple_ref()));
+    if opts.rename_duplicates {
+        repairs.push(("duplicate entries renamed", fs.repair_duplicate_names()));
+    }
+    repairs.push(("\".\" and \"..\" entries rewritten", fs.repair_dot_entries()));
+    let unlinked = match opts.unlinked {
+        Unlinked::Free => "unlinked inodes freed",
+        Unlinked::Reconnect => "unlinked inodes reconnected to /lost+found",
+    };
+    repairs.push((unlinked, fs.repair_unlinked(opts.unlinked)));
+    repairs.push(("orphaned inodes reconnected to /lost+found", fs.repair_orphans()));
+    repairs.push(("link counts", fs.repair_nlink()));
+    // The bitmap must be rebuilt after every other repair allocates or frees blocks
+    repairs.push(("bitmap bits", fs.repair_bitmap()));
+    repairs
+}
+
+fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().collect();
+    let opts = match parse_args(&args[1..]) {
+        Some(opts) => opts,
+        None => {
+            eprintln!("Usage: {} {}", args[0], USAGE);
+            return ExitCode::from(EXIT_USAGE);
+        }
+    };
+    let mut out = Output::new(opts.format, &opts.path);
+
+    /* Parse */
+    out.phase("[1/7]", &LOOKING_GLASS, "Parsing xv6 filesystem image...");
+    let mut img = match parser::read_img(&opts.path) {
+        Ok(img) => img,
+        Err(e) => {
+            out.fatal(format!("failed to read {}: {}", opts.path, e));
+            return ExitCode::from(out.finish().exit_code());
+        }
+    };
+    let mut fs = match parser::parse_fs(&img, opts.mode) {
+        Ok(fs) => fs,
+        Err(e) => {
+            out.fatal(e.to_string());
+            return ExitCode::from(out.finish().exit_code());
+        }
+    };
+
+    /* Replay the log */
+    if opts.replay_log {
+        out.phase("[+]", &LOG, "Replaying log...");
+        let installed = writer::replay_log(&fs, &mut img);
+        out.repaired("logged blocks installed", installed);
+        // --output is written even if nothing is installed
+        if installed > 0 || opts.output.is_some() {
+            if let Err(e) = save(&img, &opts) {
+                out.fatal(e);
+                return ExitCode::from(out.finish().exit_code());
+            }
+        }
+        if installed > 0 {
+            fs = match parser::parse_fs(&img, opts.mode) {
+                Ok(fs) => fs,
+                Err(e) => {
+                    out.fatal(format!("image cannot be parsed after replaying the log: {}", e));
+                    return ExitCode::from(out.finish().exit_code());
+                }
+            };
+        }
     }
+
+    /* Check */
+    for phase in PHASES.iter() {
+        out.phase(phase.step, phase.emoji, phase.title);
+        let errors = (phase.check)(&fs, &opts);
+        out.report(&fs, phase.id, &errors);
+    }
+
+    /* Repair */
+    let blockers = fs.repair_blockers();
+    if opts.repair && out.has_errors() && !blockers.is_empty() {
+        let reasons: Vec<String> = blockers.iter().map(|e| e.to_string()).collect();
+        out.refuse_repair(reasons.join("; "));
+    } else if opts.repair && out.has_errors() {
+        out.phase("[+]", &WRENCH, "Repairing...");
+        for (what, count) in repair(&mut fs, &opts) {
+            out.repaired(what, count);
+        }
+
+        let mut repaired = img.clone();
+        writer::write_fs(&fs, &mut repaired);
+        if let Err(e) = save(&repaired, &opts) {
+            out.fatal(e);
+            return ExitCode::from(out.finish().exit_code());
+        }
+
+        // Check the repaired image again to see what is left
+        let fs = match parser::parse_fs(&repaired, opts.mode) {
+            Ok(fs) => fs,
+            Err(e) => {
+                out.fatal(format!("repaired image cannot be parsed: {}", e));
+                return ExitCode::from(out.finish().exit_code());
+            }
+        };
+        let remaining = PHASES
+            .iter()
+            .flat_map(|phase| (phase.check)(&fs, &opts))
+            .filter(|e| e.severity() == Severity::Error)
+            .count();
+        out.rechecked(remaining);
+    }
+
+    ExitCode::from(out.finish().exit_code())
 }
@@ -0,0 +1,222 @@
+use console::{style, Emoji};
+use serde::Serialize;
+use xv6_fsck::fs::error::{FSError, Finding, Severity};
+use xv6_fsck::fs::FS;
+
+static SPARKLE: Emoji<'_, '_> = Emoji("✨", ":-)");
+static WARNING: Emoji<'_, '_> = Emoji("⚠️ ", ":-|");
+static ERROR: Emoji<'_, '_> = Emoji("❌", ":-(");
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    // Decorated text for terminals
+    Human,
+    // A single JSON document
+    Json,
+    // One JSON object per line
+    Jsonl,
+}
+
+impl Format {
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "human" => Some(Format::Human),
+            "json" => Some(Format::Json),
+            "jsonl" => Some(Format::Jsonl),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Verdict {
+    Clean,
+    // Only findings with Severity::Warning
+    Warnings,
+    // All errors were repaired, or the image was modified without errors
+    Corrected,
+    Errors,
+    // The image could not be read or parsed
+    Unreadable,
+}
+
+impl Verdict {
+    // Exit codes of e2fsck: 0 no errors, 1 errors corrected, 4 errors left uncorrected, 8 operational error
+    pub fn exit_code(&self) -> u8 {
+        match self {
+            Verdict::Clean | Verdict::Warnings => 0,
+            Verdict::Corrected => 1,
+            Verdict::Errors => 4,
+            Verdict::Unreadable => 8,
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct Record<'a> {
+    phase: &'static str,
+    #[serde(flatten)]
+    finding: &'a Finding,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Repair {
+    pub what: &'static str,
+    pub count: usize,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Summary {
+    pub image: String,
+    pub errors: usize,
+    pub warnings: usize,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub repairs: Vec<Repair>,
+    // Reason why --repair was not attempted
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub repair_refused: Option<String>,
+    // Errors found in the repaired image
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub remaining_errors: Option<usize>,
+    pub verdict: Verdict,
+    // Reason why the image is unreadable
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub fatal: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+struct SummaryLine<'a> {
+    summary: &'a Summary,
+}
+
+#[derive(Debug, Serialize)]
+struct Document<'a> {
+    findings: Vec<Record<'a>>,
+    summary: &'a Summary,
+}
+
+pub struct Output {
+    format: Format,
+    // (phase, finding)
+    findings: Vec<(&'static str, Finding)>,
+    summary: Summary,
+}
+
+impl Output {
+    pub fn new(format: Format, image: &str) -> Self {
+        Output {
+            format,
+            findings: vec![],
+            summary: Summary {
+                image: image.to_string(),
+                errors: 0,
+                warnings: 0,
+                repairs: vec![],
+                repair_refused: None,
+                remaining_errors: None,
+                verdict: Verdict::Clean,
+                fatal: None,
+            },
+        }
+    }
+
+    // Announce the start of a phase (human format only)
+    pub fn phase(&self, step: &str, emoji: &Emoji, title: &str) {
+        if self.format == Format::Human {
+            println!("{} {} {}", style(step).bold().dim(), emoji, title);
+        }
+    }
+
+    pub fn report(&mut self, fs: &FS, phase: &'static str, errors: &[FSError]) {
+        for e in errors {
+            let finding = fs.finding(e);
+            match finding.severity {
+                Severity::Warning => self.summary.warnings += 1,
+                Severity::Error => self.summary.errors += 1,
+            }
+            match self.format {
+                Format::Human => match finding.severity {
+                    Severity::Warning => eprintln!("{}: {}", style("warning").bold().yellow(), style(e).bold()),
+                    Severity::Error => eprintln!("{}: {}", style("error").bold().red(), style(e).bold()),
+                },
+                Format::Json => {}
+                Format::Jsonl => println!("{}", serde_json::to_string(&Record { phase, finding: &finding }).unwrap()),
+            }
+            self.findings.push((phase, finding));
+        }
+    }
+
+    pub fn has_errors(&self) -> bool {
+        self.summary.errors > 0
+    }
+
+    pub fn repaired(&mut self, what: &'static str, count: usize) {
+        if self.format == Format::Human && count > 0 {
+            println!("{}: {} {}", style("repaired").bold().green(), count, what);
+        }
+        self.summary.repairs.push(Repair { what, count });
+    }
+
+    // Errors are left as they are, since repairing would damage the image
+    pub fn refuse_repair(&mut self, reason: String) {
+        if self.format == Format::Human {
+            eprintln!("{}: {}", style("repair refused").bold().red(), style(&reason).bold());
+        }
+        self.summary.repair_refused = Some(reason);
+    }
+
+    pub fn rechecked(&mut self, remaining_errors: usize) {
+        self.summary.remaining_errors = Some(remaining_errors);
+    }
+
+    // The image cannot be checked at all
+    pub fn fatal(&mut self, message: String) {
+        if self.format == Format::Human {
+            eprintln!("{}: {}", style("error").bold().red(), style(&message).bold());
+        }
+        self.summary.fatal = Some(message);
+    }
+
+    pub fn finish(mut self) -> Verdict {
+        self.summary.verdict = if self.summary.fatal.is_some() {
+            Verdict::Unreadable
+        } else if self.summary.remaining_errors == Some(0) {
+            Verdict::Corrected
+        } else if self.summary.errors > 0 {
+            Verdict::Errors
+        } else if self.summary.repairs.iter().any(|repair| repair.count > 0) {
+            // The image was modified without errors, e.g. by replaying the log
+            Verdict::Corrected
+        } else if self.summary.warnings > 0 {
+            Verdict::Warnings
+        } else {
+            Verdict::Clean
+        };
+
+        match self.format {
+            Format::Human => match self.summary.verdict {
+                Verdict::Clean => println!("{} {}", SPARKLE, style("No errors").bold()),
+                Verdict::Warnings => println!("{} {}", WARNING, style("Found warnings").bold()),
+                Verdict::Corrected if self.summary.errors == 0 => println!("{} {}", SPARKLE, style("Image modified, no errors").bold()),
+                Verdict::Corrected => println!("{} {}", SPARKLE, style("Repaired all errors").bold()),
+                Verdict::Errors => match self.summary.remaining_errors {
+                    Some(n) => println!("{} {}", ERROR, style(format!("{} errors left after repair", n)).bold()),
+                    None => println!("{} {}", ERROR, style("Found errors").bold()),
+                },
+                Verdict::Unreadable => println!("{} {}", ERROR, style("Failed to parse image").bold()),
+            },
+            Format::Json => {
+                let findings = self
+                    .findings
+                    .iter()
+                    .map(|(phase, finding)| Record { phase, finding })
+                    .collect();
+                let document = Document { findings, summary: &self.summary };
+                println!("{}", serde_json::to_string_pretty(&document).unwrap());
+            }
+            Format::Jsonl => println!("{}", serde_json::to_string(&SummaryLine { summary: &self.summary }).unwrap()),
+        }
+        self.summary.verdict
+    }
+}
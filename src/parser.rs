@@ -1,4 +1,7 @@
+pub mod error;
+
 use std::fs::File;
+use std::io;
 use std::io::Read;
 use std::mem::size_of;
 use nom::{combinator, Parser};
@@ -7,13 +10,32 @@ use nom::IResult;
 use nom::multi;
 use nom::number::complete::{le_u16, le_u32};
 use crate::fs;
-use crate::fs::{SuperBlock, Dinode, FileType, BlockStatus, FS, Dirent};
+use crate::fs::{SuperBlock, LogHeader, Geometry, BlockNo, Dinode, FileType, BlockStatus, FS, Dirent};
+use crate::parser::error::ParseError;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ParseMode {
+    // Abort on the first corrupt inode or address
+    #[default]
+    Strict,
+    // Keep corrupt inodes as FileType::Invalid and drop invalid addresses,
+    // leaving them to be reported by the checkers in fs::check
+    Lenient,
+}
 
-pub fn read_img(path: &str) -> Vec<u8> {
-    let mut file = File::open(path).expect("Failed to open file");
+pub fn read_img(path: &str) -> io::Result<Vec<u8>> {
+    let mut file = File::open(path)?;
     let mut buf = Vec::new();
-    file.read_to_end(&mut buf).expect("Failed to read file");
-    buf
+    file.read_to_end(&mut buf)?;
+    Ok(buf)
+}
+
+// Convert a nom error while reading `what` from `input` into ParseError::Truncated
+// The parsers below only fail with nom errors when the input is too short
+fn truncated<'a>(img: &[u8], input: &[u8], what: &'static str) -> impl Fn(nom::Err<nom::error::Error<&'a [u8]>>) -> ParseError {
+    let offset = img.len() - input.len();
+    let len = img.len();
+    move |_| ParseError::Truncated { what, offset, block: offset / fs::BSIZE, len }
 }
 
 fn parse_superblock(input: &[u8]) -> IResult<&[u8], SuperBlock> {
@@ -21,10 +43,6 @@ fn parse_superblock(input: &[u8]) -> IResult<&[u8], SuperBlock> {
         multi::count(le_u32, 8),
         |v| {
             let magic = v[0];
-            if magic != fs::FSMAGIC {
-                // TODO: Error handling
-                panic!("invalid magic number");
-            }
             let size = v[1];
             let nblocks = v[2];
             let ninodes = v[3];
@@ -45,72 +63,98 @@ fn parse_superblock(input: &[u8]) -> IResult<&[u8], SuperBlock> {
     Ok((input, superblock))
 }
 
-fn parse_file_type(input: &[u8]) -> IResult<&[u8], FileType> {
-    let (input, typ) = le_u16(input)?;
-    let typ = match typ {
+fn parse_log_header(input: &[u8]) -> IResult<&[u8], LogHeader> {
+    let (input, n) = le_u32(input)?;
+    let (input, block) = multi::count(le_u32, fs::BSIZE / size_of::<u32>() - 1).parse(input)?;
+    Ok((input, LogHeader::new(n, block)))
+}
+
+fn parse_file_type(typ: u16) -> FileType {
+    match typ {
         0 => FileType::UNUSED,
         1 => FileType::DIR,
         2 => FileType::FILE,
         3 => FileType::DEV,
-        // TODO: Error handling
-        _ => panic!("invalid file type"),
-    };
-    Ok((input, typ))
+        _ => FileType::Invalid(typ),
+    }
 }
 
-fn parse_addrs(input: &[u8], addrs_offset: u32) -> IResult<&[u8], [Option<u32>; fs::NDIRECT + 1]> {
-    let (input, addrs) = multi::count(le_u32, fs::NDIRECT + 1).parse(input)?;
-    let addrs = addrs
-        .into_iter()
-        .map(|x|
-            // TODO: Error handling
-            if x == 0 { None } else if x >= addrs_offset { Some(x - addrs_offset) } else { panic!("invalid address") })
-        .collect::<Vec<Option<u32>>>()
-        .try_into()
-        .unwrap();
-    Ok((input, addrs))
-}
-
-fn parse_dinode(input: &[u8], addrs_offset: u32) -> IResult<&[u8], Dinode> {
-    let (input, typ) = parse_file_type(input)?;
-    let (input, major) = le_u16(input)?;
-    let (input, minor) = le_u16(input)?;
-    let (input, nlink) = le_u16(input)?;
-    let (input, size) = le_u32(input)?;
-    let (input, addrs) = parse_addrs(input, addrs_offset)?;
-    Ok((input, Dinode::new(typ, major, minor, nlink, size, addrs)))
-}
-
-fn parse_dinodes(input: &[u8], blocks: usize, addrs_offset: u32) -> IResult<&[u8], Vec<Dinode>> {
-    let n = blocks * fs::IPB;
-    let mut parser = multi::count(|i| parse_dinode(i, addrs_offset), n);
-    parser.parse(input)
+// Check that a block number on disk refers to a data block (0 means no block)
+// Returns Err(addr) if the block is outside the data region
+fn to_block_no(addr: u32, geo: &Geometry) -> Result<Option<BlockNo>, u32> {
+    if addr == 0 {
+        Ok(None)
+    } else if geo.in_data_region(addr) {
+        Ok(Some(BlockNo(addr)))
+    } else {
+        Err(addr)
+    }
 }
 
-fn parse_bit(input: (&[u8], usize)) -> IResult<(&[u8], usize), BlockStatus> {
-    // HACK: Read from the rightmost bit
-    // Must be checked for correctness
-    let (bytes, offset) = input;
+fn parse_addrs(input: &[u8]) -> IResult<&[u8], Vec<u32>> {
+    multi::count(le_u32, fs::NDIRECT + 1).parse(input)
+}
+
+fn parse_dinode<'a>(img: &'a [u8], input: &'a [u8], inum: u16, geo: &Geometry, mode: ParseMode) -> Result<(&'a [u8], Dinode), ParseError> {
+    let offset = img.len() - input.len();
+    let block = offset / fs::BSIZE;
+    let eof = truncated(img, input, "inode");
 
-    let first_byte = bytes[0];
-    let bit = (first_byte >> offset) & 1;
-    let status = BlockStatus::new(bit);
+    let (input, typ) = le_u16(input).map_err(&eof)?;
+    let typ = match parse_file_type(typ) {
+        FileType::Invalid(found) if mode == ParseMode::Strict => {
+            return Err(ParseError::InvalidFileType { inum, block, offset, found });
+        }
+        typ => typ,
+    };
+    let (input, major) = le_u16(input).map_err(&eof)?;
+    let (input, minor) = le_u16(input).map_err(&eof)?;
+    let (input, nlink) = le_u16(input).map_err(&eof)?;
+    let (input, size) = le_u32(input).map_err(&eof)?;
+    let (input, addrs) = parse_addrs(input).map_err(&eof)?;
 
-    // next input
-    let offset = (offset + 1) % 8;
-    let bytes = if offset == 0 { &bytes[1..] } else { bytes };
-    let input = (bytes, offset);
+    let mut data_addrs = [None; fs::NDIRECT + 1];
+    let mut raw_addrs = [0; fs::NDIRECT + 1];
+    for (slot, addr) in addrs.into_iter().enumerate() {
+        raw_addrs[slot] = addr;
+        data_addrs[slot] = match to_block_no(addr, geo) {
+            Ok(addr) => addr,
+            Err(_) if mode == ParseMode::Lenient => None,
+            Err(addr) => return Err(ParseError::InvalidAddress {
+                inum,
+                block,
+                offset,
+                slot,
+                addr,
+                datastart: geo.datastart,
+                size: geo.size,
+            }),
+        };
+    }
+
+    Ok((input, Dinode::new(typ, major, minor, nlink, size, data_addrs, raw_addrs)))
+}
 
-    Ok((input, status))
+fn parse_dinodes<'a>(img: &'a [u8], input: &'a [u8], geo: &Geometry, mode: ParseMode) -> Result<(&'a [u8], Vec<Dinode>), ParseError> {
+    let n = geo.ninodeblocks as usize * fs::IPB;
+    let mut input = input;
+    let mut dinodes = Vec::with_capacity(n);
+    for inum in 0..n {
+        let (rest, dinode) = parse_dinode(img, input, inum as u16, geo, mode)?;
+        input = rest;
+        dinodes.push(dinode);
+    }
+    Ok((input, dinodes))
 }
 
-// TODO: refactor
 fn parse_bitmap(input: &[u8], blocks: usize) -> IResult<&[u8], Vec<BlockStatus>> {
-    let n = blocks * fs::BPB;
-    let mut parser = multi::count(parse_bit, n);
-    let offset = 0;
-    let (input, output) = parser((input, offset)).unwrap();
-    Ok((input.0, output))
+    let (input, bytes) = bytes::complete::take(blocks * fs::BSIZE).parse(input)?;
+    // The i-th bit of the bitmap is (i % 8)-th bit from the LSB of (i / 8)-th byte
+    let bitmap = bytes
+        .iter()
+        .flat_map(|byte| (0..8).map(move |offset| BlockStatus::new((byte >> offset) & 1)))
+        .collect();
+    Ok((input, bitmap))
 }
 
 fn read_block(input: &[u8]) -> IResult<&[u8], Vec<u8>> {
@@ -124,56 +168,100 @@ fn parse_data(input: &[u8], blocks: usize) -> IResult<&[u8], Vec<Vec<u8>>> {
     parser.parse(input)
 }
 
-fn skip_block(input: &[u8], n: usize) -> IResult<&[u8], ()> {
-    let mut parser = bytes::complete::take(fs::BSIZE * n);
-    let (input, _) = parser.parse(input)?;
-    Ok((input, ()))
+// Seek to the block `start`, checking that the `n` blocks of `what` from there are inside the image
+fn seek<'a>(img: &'a [u8], start: u32, n: u32, what: &'static str) -> Result<&'a [u8], ParseError> {
+    let offset = start as usize * fs::BSIZE;
+    let end = offset + n as usize * fs::BSIZE;
+    if end > img.len() {
+        return Err(ParseError::Truncated { what, offset, block: start as usize, len: img.len() });
+    }
+    Ok(&img[offset..])
 }
 
-fn parse_dirname(input: &[u8]) -> IResult<&[u8], String> {
+fn parse_dirname(input: &[u8]) -> IResult<&[u8], [u8; fs::DIRSIZ]> {
     let mut parser = bytes::complete::take(fs::DIRSIZ);
     let (input, dirname) = parser.parse(input)?;
-    let dirname = std::str::from_utf8(dirname).unwrap();
-    let dirname = dirname.trim_end_matches('\0').to_string();
-    Ok((input, dirname))
+    Ok((input, dirname.try_into().unwrap()))
 }
 
-fn parse_dirent(input: &[u8]) -> IResult<&[u8], Option<Dirent>> {
+fn parse_dirent(input: &[u8], offset: usize) -> IResult<&[u8], Option<Dirent>> {
     let (input, inum) = le_u16::<_, nom::error::Error<_>>(input)?;
     let (input, name) = parse_dirname(input)?;
-    let dirent = if inum == 0 { None } else { Some(Dirent::new(inum, name)) };
+    let dirent = if inum == 0 { None } else { Some(Dirent::new(inum, name, offset)) };
     Ok((input, dirent))
 }
 
-pub fn parse_dirents(input: &[u8]) -> Vec<Option<Dirent>> {
-    let dirent_per_block = fs::BSIZE / fs::DIRENTSIZE;
-    let mut parser = multi::count(parse_dirent, dirent_per_block);
-    let (_, dirents) = parser.parse(input).unwrap();
-    dirents
+// Parse the entries in `input`, which starts at byte `start` of the directory
+// A trailing partial entry is ignored
+pub fn parse_dirents(input: &[u8], start: usize) -> Vec<Option<Dirent>> {
+    input
+        .chunks_exact(fs::DIRENTSIZE)
+        .enumerate()
+        .map(|(i, chunk)| parse_dirent(chunk, start + i * fs::DIRENTSIZE).unwrap().1)
+        .collect()
 }
 
-pub fn parse_fs(input: &[u8]) -> FS {
-    let (input, _) = skip_block(input, 1).unwrap();
-    let (input, sb) = parse_superblock(input).unwrap();
+pub fn parse_fs(img: &[u8], mode: ParseMode) -> Result<FS, ParseError> {
+    let input = seek(img, 1, 1, "superblock")?;
+    let (_, sb) = parse_superblock(input).map_err(truncated(img, input, "superblock"))?;
+    if sb.magic != fs::FSMAGIC {
+        return Err(ParseError::InvalidMagic { offset: fs::BSIZE, found: sb.magic, expected: fs::FSMAGIC });
+    }
+    if sb.nblocks > sb.size {
+        return Err(ParseError::InvalidDataRegion { nblocks: sb.nblocks, size: sb.size });
+    }
+    let geo = Geometry::new(&sb);
 
-    let ninodeblocks: usize = sb.ninodes as usize / fs::IPB + 1;
-    let nbitmap: usize = sb.size as usize / fs::BPB + 1;
-    let datastart: u32 = sb.size - sb.nblocks;
+    // A broken logstart is reported by SuperBlock::check_fields, so the log is just ignored here
+    let log = match seek(img, geo.logstart, 1, "log header") {
+        Ok(input) if geo.nlog > 0 => Some(parse_log_header(input).map_err(truncated(img, input, "log header"))?.1),
+        _ => None,
+    };
+
+    let input = seek(img, geo.inodestart, geo.ninodeblocks, "inode blocks")?;
+    let (_, dinodes) = parse_dinodes(img, input, &geo, mode)?;
+    let input = seek(img, geo.bmapstart, geo.nbitmap, "bitmap blocks")?;
+    let (_, bitmap) = parse_bitmap(input, geo.nbitmap as usize).map_err(truncated(img, input, "bitmap blocks"))?;
+    let input = seek(img, geo.datastart, geo.nblocks, "data blocks")?;
+    let (_, data) = parse_data(input, geo.nblocks as usize).map_err(truncated(img, input, "data blocks"))?;
 
-    let (input, _) = skip_block(input, sb.nlog as usize).unwrap();
-    let (input, dinodes) = parse_dinodes(input, ninodeblocks, datastart).unwrap();
-    let (input, bitmap) = parse_bitmap(input, nbitmap).unwrap();
-    let (_, data) = parse_data(input, sb.nblocks as usize).unwrap();
+    // Reject broken indirect blocks here, so that FS can follow them safely
+    if mode == ParseMode::Strict {
+        for dinode in dinodes.iter() {
+            if let Some(addr) = dinode.addrs[fs::NDIRECT] {
+                parse_indirect_reference_block(&data[geo.data_index(addr).0 as usize], addr, &geo, mode)?;
+            }
+        }
+    }
+
+    Ok(FS::new(sb, log, dinodes, bitmap, data))
+}
 
-    FS::new(sb, dinodes, bitmap, data)
+// Block numbers on disk in an indirect block
+pub fn parse_indirect_block(input: &[u8]) -> Vec<u32> {
+    input
+        .chunks_exact(size_of::<u32>())
+        .take(fs::NINDIRECT)
+        .map(|x| u32::from_le_bytes(x.try_into().unwrap()))
+        .collect()
 }
 
-pub fn parse_indirect_reference_block(input: &[u8], datastart: u32) -> Vec<Option<u32>> {
-    let (_, addrs) = multi::count(le_u32::<_, nom::error::Error<_>>, fs::NINDIRECT)(input).unwrap();
-    // TODO: Refactor (Duplicate code)
-    addrs
+// `addr` is the block number of the indirect block itself
+// In lenient mode, invalid addresses are replaced with None
+pub fn parse_indirect_reference_block(input: &[u8], addr: BlockNo, geo: &Geometry, mode: ParseMode) -> Result<Vec<Option<BlockNo>>, ParseError> {
+    parse_indirect_block(input)
         .into_iter()
-        // TODO: Error handling
-        .map(|x| if x == 0 { None } else if x >= datastart { Some(x - datastart) } else { panic!("invalid address") })
-        .collect::<Vec<Option<u32>>>()
+        .enumerate()
+        .map(|(slot, x)| match to_block_no(x, geo) {
+            Ok(x) => Ok(x),
+            Err(_) if mode == ParseMode::Lenient => Ok(None),
+            Err(x) => Err(ParseError::InvalidIndirectAddress {
+                block: addr.0,
+                slot,
+                addr: x,
+                datastart: geo.datastart,
+                size: geo.size,
+            }),
+        })
+        .collect()
 }
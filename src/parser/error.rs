@@ -0,0 +1,55 @@
+use thiserror::Error;
+
+#[derive(Debug, Error, PartialEq)]
+pub enum ParseError {
+    // The image ended before `what` could be read
+    #[error("image is truncated: expected {what} at byte {offset} (block {block}), but the image is only {len} bytes long")]
+    Truncated {
+        what: &'static str,
+        offset: usize,
+        block: usize,
+        len: usize,
+    },
+
+    #[error("invalid magic number at byte {offset}: expected {expected:#x}, found {found:#x}")]
+    InvalidMagic {
+        offset: usize,
+        found: u32,
+        expected: u32,
+    },
+    // The superblock describes more data blocks than blocks in the image
+    #[error("superblock has nblocks {nblocks}, but this exceeds the image size of {size} blocks")]
+    InvalidDataRegion {
+        nblocks: u32,
+        size: u32,
+    },
+
+    // `found` is not one of the types in fs::FileType
+    #[error("{inum}-th inode (block {block}, byte {offset}) has invalid file type {found}, expected 0..=3")]
+    InvalidFileType {
+        inum: u16,
+        block: usize,
+        offset: usize,
+        found: u16,
+    },
+    // Address in dinode.addrs[slot] is neither 0 nor inside the data region
+    #[error("{inum}-th inode (block {block}, byte {offset}) refers to block {addr} in addrs[{slot}], expected 0 or a data block in {datastart}..{size}")]
+    InvalidAddress {
+        inum: u16,
+        block: usize,
+        offset: usize,
+        slot: usize,
+        addr: u32,
+        datastart: u32,
+        size: u32,
+    },
+    // Address in the slot-th entry of an indirect block is neither 0 nor inside the data region
+    #[error("indirect block {block} refers to block {addr} in slot {slot}, expected 0 or a data block in {datastart}..{size}")]
+    InvalidIndirectAddress {
+        block: u32,
+        slot: usize,
+        addr: u32,
+        datastart: u32,
+        size: u32,
+    },
+}
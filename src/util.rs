@@ -1 +1,3 @@
 pub mod graph;
+#[cfg(test)]
+pub mod testing;
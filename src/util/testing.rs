@@ -0,0 +1,43 @@
+// Fixtures for building small images in tests
+use crate::fs;
+use crate::writer::encode_dirent;
+
+// Image with 64 blocks, 16 inodes, 2 log blocks and an empty root directory
+pub fn mkfs() -> Vec<u8> {
+    let mut img = vec![0u8; 64 * fs::BSIZE];
+    let sb = [fs::FSMAGIC, 64, 57, 16, 2, 2, 4, 6];
+    for (i, v) in sb.iter().enumerate() {
+        img[fs::BSIZE + i * 4..][..4].copy_from_slice(&v.to_le_bytes());
+    }
+    // root inode: DIR, nlink 1, size BSIZE, addrs[0] = 7
+    let root = &mut img[4 * fs::BSIZE + fs::INODESIZE..];
+    root[0..2].copy_from_slice(&1u16.to_le_bytes());
+    root[6..8].copy_from_slice(&1u16.to_le_bytes());
+    root[8..12].copy_from_slice(&(fs::BSIZE as u32).to_le_bytes());
+    root[12..16].copy_from_slice(&7u32.to_le_bytes());
+    // "." and ".."
+    let dir = &mut img[7 * fs::BSIZE..];
+    dir[0..2].copy_from_slice(&fs::ROOTINO.to_le_bytes());
+    dir[2] = b'.';
+    dir[16..18].copy_from_slice(&fs::ROOTINO.to_le_bytes());
+    dir[18..20].copy_from_slice(b"..");
+    // blocks 0..=7 are in use
+    img[6 * fs::BSIZE] = 0xff;
+    img
+}
+
+// Write an inode with the given type, nlink, size and addrs
+pub fn set_inode(img: &mut [u8], inum: u16, typ: u16, nlink: u16, size: u32, addrs: &[u32]) {
+    let dinode = &mut img[4 * fs::BSIZE + inum as usize * fs::INODESIZE..][..fs::INODESIZE];
+    dinode[0..2].copy_from_slice(&typ.to_le_bytes());
+    dinode[6..8].copy_from_slice(&nlink.to_le_bytes());
+    dinode[8..12].copy_from_slice(&size.to_le_bytes());
+    for (slot, addr) in addrs.iter().enumerate() {
+        dinode[12 + slot * 4..][..4].copy_from_slice(&addr.to_le_bytes());
+    }
+}
+
+// Write the `index`-th entry of the directory block
+pub fn set_entry(img: &mut [u8], block: usize, index: usize, inum: u16, name: &str) {
+    img[block * fs::BSIZE + index * fs::DIRENTSIZE..][..fs::DIRENTSIZE].copy_from_slice(&encode_dirent(inum, name));
+}
@@ -0,0 +1,139 @@
+use crate::fs;
+use crate::fs::{Dinode, FileType, BlockStatus, FS};
+
+fn encode_file_type(typ: &FileType) -> u16 {
+    match typ {
+        FileType::UNUSED => 0,
+        FileType::DIR => 1,
+        FileType::FILE => 2,
+        FileType::DEV => 3,
+        FileType::Invalid(typ) => *typ,
+    }
+}
+
+fn write_dinode(buf: &mut [u8], dinode: &Dinode) {
+    buf[0..2].copy_from_slice(&encode_file_type(&dinode.typ).to_le_bytes());
+    buf[2..4].copy_from_slice(&dinode.major.to_le_bytes());
+    buf[4..6].copy_from_slice(&dinode.minor.to_le_bytes());
+    buf[6..8].copy_from_slice(&dinode.nlink.to_le_bytes());
+    buf[8..12].copy_from_slice(&dinode.size.to_le_bytes());
+    let addrs = buf[12..fs::INODESIZE].chunks_exact_mut(4);
+    for ((addr, raw), buf) in dinode.addrs.iter().zip(dinode.raw_addrs).zip(addrs) {
+        // Addresses dropped by lenient parsing are written back as they were
+        let block = addr.map_or(raw, |addr| addr.0);
+        buf.copy_from_slice(&block.to_le_bytes());
+    }
+}
+
+// Directory entry on disk, with the name truncated to DIRSIZ bytes
+pub fn encode_dirent(inum: u16, name: &str) -> [u8; fs::DIRENTSIZE] {
+    let mut buf = [0; fs::DIRENTSIZE];
+    buf[0..2].copy_from_slice(&inum.to_le_bytes());
+    let name = &name.as_bytes()[..name.len().min(fs::DIRSIZ)];
+    buf[2..2 + name.len()].copy_from_slice(name);
+    buf
+}
+
+// Write the regions modeled by FS (inode blocks, bitmap blocks and data blocks) back into `img`
+// The boot block, the superblock and the log are left as they are
+// `img` must be the image FS was parsed from, or a copy of it
+pub fn write_fs(fs: &FS, img: &mut [u8]) {
+    let geo = &fs.geometry;
+
+    let inodes = &mut img[geo.inodestart as usize * fs::BSIZE..][..geo.ninodeblocks as usize * fs::BSIZE];
+    for (dinode, buf) in fs.dinodes.iter().zip(inodes.chunks_exact_mut(fs::INODESIZE)) {
+        write_dinode(buf, dinode);
+    }
+
+    let bitmap = &mut img[geo.bmapstart as usize * fs::BSIZE..][..geo.nbitmap as usize * fs::BSIZE];
+    for (bits, byte) in fs.bitmap.chunks(8).zip(bitmap.iter_mut()) {
+        *byte = bits
+            .iter()
+            .enumerate()
+            .filter(|(_, bit)| **bit == BlockStatus::Allocated)
+            .fold(0, |byte, (offset, _)| byte | (1 << offset));
+    }
+
+    // Data blocks overlapping the metadata would overwrite the inodes and bitmap written above
+    let data = &mut img[geo.datastart as usize * fs::BSIZE..][..geo.nblocks as usize * fs::BSIZE];
+    for (addr, (block, buf)) in geo.data_blocks().zip(fs.data.iter().zip(data.chunks_exact_mut(fs::BSIZE))) {
+        if addr.0 >= geo.metadata_end() {
+            buf.copy_from_slice(block);
+        }
+    }
+}
+
+// Install the committed blocks of the log into their home locations and clear the log, like install_trans in xv6
+// Nothing is written if the log header is broken (see FS::check_log)
+// FS must be parsed again from `img` afterwards
+// Returns the number of installed blocks
+pub fn replay_log(fs: &FS, img: &mut [u8]) -> usize {
+    let geo = &fs.geometry;
+    let log = match &fs.log {
+        Some(log) => log,
+        None => return 0,
+    };
+    let n = log.n as usize;
+    if log.n > log.capacity(geo) || !log.block[..n].iter().all(|block| geo.is_loggable(*block)) {
+        return 0;
+    }
+    if (geo.logstart as usize + 1 + n) * fs::BSIZE > img.len() {
+        return 0;
+    }
+
+    for (i, block) in log.block[..n].iter().enumerate() {
+        let src = (geo.logstart as usize + 1 + i) * fs::BSIZE;
+        let dst = *block as usize * fs::BSIZE;
+        img.copy_within(src..src + fs::BSIZE, dst);
+    }
+    let header = geo.logstart as usize * fs::BSIZE;
+    img[header..header + 4].copy_from_slice(&0u32.to_le_bytes());
+    n
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser;
+    use crate::parser::ParseMode;
+    use crate::util::testing::mkfs;
+
+    #[test]
+    fn roundtrip() {
+        let img = mkfs();
+        let fs = parser::parse_fs(&img, ParseMode::Strict).unwrap();
+        let mut written = vec![0u8; img.len()];
+        written[..2 * fs::BSIZE].copy_from_slice(&img[..2 * fs::BSIZE]);
+        write_fs(&fs, &mut written);
+        assert!(written == img);
+    }
+
+    #[test]
+    fn repair_bitmap() {
+        let mut img = mkfs();
+        // data block 7 is free, and 8 is allocated
+        img[6 * fs::BSIZE] = 0x7f;
+        img[6 * fs::BSIZE + 1] = 0x01;
+        let mut fs = parser::parse_fs(&img, ParseMode::Strict).unwrap();
+        assert_eq!(fs.check_bitmap().len(), 2);
+        assert_eq!(fs.repair_bitmap(), 2);
+        write_fs(&fs, &mut img);
+        assert_eq!(img[6 * fs::BSIZE], 0xff);
+        assert_eq!(img[6 * fs::BSIZE + 1], 0);
+    }
+
+    #[test]
+    fn replay_log() {
+        let mut img = mkfs();
+        // block 7 with "." renamed to "a" is committed in the log
+        img.copy_within(7 * fs::BSIZE..8 * fs::BSIZE, 3 * fs::BSIZE);
+        img[3 * fs::BSIZE + 2] = b'a';
+        img[2 * fs::BSIZE..][..8].copy_from_slice(&[1, 0, 0, 0, 7, 0, 0, 0]);
+        let fs = parser::parse_fs(&img, ParseMode::Strict).unwrap();
+        assert_eq!(fs.check_log().len(), 1);
+        assert_eq!(super::replay_log(&fs, &mut img), 1);
+        assert_eq!(img[7 * fs::BSIZE + 2], b'a');
+        let fs = parser::parse_fs(&img, ParseMode::Strict).unwrap();
+        assert_eq!(fs.log.unwrap().n, 0);
+    }
+}